@@ -2,9 +2,20 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
+use crate::config::TempUnit;
+use crate::log_format::LogFormat;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    /// Log output format, the `RUST_LOG` filter applies to both
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Log without ANSI colors, also when `NO_COLOR` is set
+    #[arg(long)]
+    pub no_color: bool,
+
     /// MQTT broker host
     #[arg(short = 'H', long, env = "MQTT_HOST")]
     pub host: Option<String>,
@@ -25,27 +36,221 @@ pub struct Args {
     #[arg(short = 'P', long, env = "MQTT_PASSWORD")]
     pub password: Option<String>,
 
-    /// Toml configuration file for sensors
+    /// Read the MQTT username from this file, e.g. a Docker secret
+    #[arg(long, env = "MQTT_USERNAME_FILE", conflicts_with = "username")]
+    pub username_file: Option<PathBuf>,
+
+    /// Read the MQTT password from this file so it doesn't show up in `ps`, e.g. a Docker secret
+    #[arg(long, env = "MQTT_PASSWORD_FILE", conflicts_with = "password")]
+    pub password_file: Option<PathBuf>,
+
+    /// Largest MQTT packet in bytes that is sent or accepted, raise it when a large discovery
+    /// payload does not show up in Home Assistant
+    #[arg(long, default_value = "10240", env = "MQTT_MAX_PACKET_SIZE")]
+    pub max_packet_size: usize,
+
+    /// Docker daemon to read container stats from, `unix:///path/docker.sock` or
+    /// `tcp://host:2375`. Defaults to the local socket
+    #[arg(long, env = "DOCKER_HOST")]
+    pub docker_host: Option<String>,
+
+    /// Configuration file for sensors, toml, json or yaml (by extension). Can be repeated,
+    /// later files override sensors from earlier ones
     #[arg(short = 'c', long)]
-    pub config_file: Option<PathBuf>,
+    pub config_file: Vec<PathBuf>,
 
-    /// Dump overwriteable sensor settings to file. You cant change how the default sensors work.
+    /// Dump overwriteable sensor settings to file. Built-in command sensors can also get a new
+    /// `command`/`args`, their output is still parsed the same way.
     #[arg(long)]
     pub sensor_dump: Option<PathBuf>,
 
+    /// Print the sensors that would be published and exit. Prints JSON with --json-output
+    #[arg(long)]
+    pub list_sensors: bool,
+
+    /// Print the JSON Schema of the config file and exit, for editor validation and completion
+    #[arg(long)]
+    pub print_schema: bool,
+
+    /// Print tab completions for SHELL to stdout and exit, e.g. into
+    /// /etc/bash_completion.d/unraid-mqtt-stats
+    #[arg(long, value_enum, value_name = "SHELL")]
+    pub generate_completion: Option<clap_complete::Shell>,
+
+    /// Check that the MQTT broker (when --host is set) and Docker can be reached, then exit
+    /// non-zero if either can't. Publishes nothing, for Docker HEALTHCHECK or systemd
+    #[arg(long)]
+    pub health_check: bool,
+
+    /// Connect to the MQTT broker and log what would be published without publishing anything
+    #[arg(long, conflicts_with = "json_output")]
+    pub dry_run: bool,
+
     /// JSON output mode (outputs stats to stdout instead of MQTT)
     #[arg(long)]
     pub json_output: bool,
 
+    /// With --json-output, print all messages as one JSON array at the end instead of one per line
+    #[arg(long, requires = "json_output")]
+    pub json_array: bool,
+
+    /// InfluxDB line protocol output mode (outputs stats to stdout instead of MQTT)
+    #[arg(long, conflicts_with = "json_output")]
+    pub influx_output: bool,
+
+    /// Serve Prometheus metrics on this address, e.g. 0.0.0.0:9100. Runs alongside MQTT when --host is set
+    #[arg(long)]
+    pub prometheus_listen: Option<String>,
+
     /// Home Assistant discovery prefix
     #[arg(long, default_value = "homeassistant")]
     pub discovery_prefix: String,
 
+    /// MQTT QoS (0-2) of discovery messages, state messages always use 1
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub discovery_qos: u8,
+
+    /// Retain discovery messages so Home Assistant finds the sensors after it restarts
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub discovery_retain: bool,
+
+    /// Publish all values as one retained JSON object on this topic once per cycle instead of
+    /// a topic per sensor, discovery uses a `value_template` for each entity
+    #[arg(long)]
+    pub json_state_topic: Option<String>,
+
     /// Device name for Home Assistant
     #[arg(long, default_value = "unraid")]
     pub device_name: String,
 
+    /// Topic safe id used in MQTT topics and unique ids, defaults to `unraid_<device-name>`
+    #[arg(long, value_parser = parse_node_id)]
+    pub node_id: Option<String>,
+
+    /// Device model for Home Assistant
+    #[arg(long, default_value = "Unraid Server")]
+    pub device_model: String,
+
+    /// Device manufacturer for Home Assistant
+    #[arg(long, default_value = "Lime Technology")]
+    pub device_manufacturer: String,
+
+    /// Device software version for Home Assistant. Read from /etc/unraid-version when not set
+    #[arg(long)]
+    pub device_sw_version: Option<String>,
+
+    /// Appended to the node id in the Home Assistant device identifiers so servers sharing a
+    /// device name stay separate devices. Defaults to /etc/machine-id or the hostname, `none`
+    /// uses the plain node id. Changing it creates a new device in Home Assistant
+    #[arg(long)]
+    pub device_id_suffix: Option<String>,
+
+    /// Seconds without an update before Home Assistant marks a sensor unavailable.
+    /// Sensors can override this with `expire_after` in the config file. Defaults to 3x `--interval`
+    #[arg(long)]
+    pub expire_after: Option<u32>,
+
+    /// Run as a daemon, publishing stats every N seconds
+    #[arg(long, conflicts_with = "once")]
+    pub interval: Option<u32>,
+
+    /// Publish an availability topic with an MQTT last will, the default with --interval.
+    /// One-shot runs only do with this flag, the sensors then go unavailable when a run
+    /// dies before it has finished publishing
+    #[arg(long, overrides_with = "no_availability")]
+    pub availability: bool,
+
+    /// Don't publish availability with --interval either, Home Assistant keeps showing the last
+    /// values after the process stops
+    #[arg(long, overrides_with = "availability")]
+    pub no_availability: bool,
+
+    /// Publish once and exit, the default without --interval
+    #[arg(long)]
+    pub once: bool,
+
+    /// Don't build sensors whose id starts with this, e.g. `docker` skips every docker
+    /// API call (repeatable)
+    #[arg(long = "disable", value_name = "PREFIX")]
+    pub disabled_prefixes: Vec<String>,
+
+    /// Only add hardware temperature sensors whose label contains this (repeatable)
+    #[arg(long)]
+    pub component_include: Vec<String>,
+
+    /// Skip hardware temperature sensors whose label contains this (repeatable)
+    #[arg(long)]
+    pub component_exclude: Vec<String>,
+
+    /// Decimals published for the cpu and memory usage percentages. Publish more and set
+    /// `suggested_display_precision` in the config to round only in Home Assistant
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=6))]
+    pub precision: u8,
+
+    /// Unit of the temperature sensors, `F` converts the °C readings to °F
+    #[arg(long, value_enum, default_value_t = TempUnit::C)]
+    pub temp_unit: TempUnit,
+
+    /// Maximum number of sensors collected at the same time
+    #[arg(long, default_value = "8")]
+    pub collect_concurrency: usize,
+
+    /// Retain state messages so Home Assistant has a value right after it restarts.
+    /// Sensors can override this with `retain` in the config file
+    #[arg(long)]
+    pub retain_state: bool,
+
     /// Skip Home Assistant discovery messages
     #[arg(long)]
     pub skip_discovery: bool,
+
+    /// Remove every retained discovery message for this device so Home Assistant deletes the entities
+    #[arg(long, conflicts_with = "remove_stale_discovery")]
+    pub remove_discovery: bool,
+
+    /// Remove retained discovery messages for sensors that no longer exist or are disabled
+    #[arg(long)]
+    pub remove_stale_discovery: bool,
+}
+
+impl Args {
+    /// Availability is published when running as a daemon or with `--availability`, by
+    /// default a one-shot run has no connection left for Home Assistant to watch.
+    pub fn availability_topic(&self) -> Option<String> {
+        if self.dry_run || self.no_availability {
+            return None;
+        }
+        (self.interval.is_some() || self.availability)
+            .then(|| format!("{}/availability", self.node_id()))
+    }
+
+    /// Switch commands are only received while running as a daemon that publishes, a
+    /// one-shot run is gone before Home Assistant sends one.
+    pub fn handles_switches(&self) -> bool {
+        self.interval.is_some() && !self.dry_run
+    }
+
+    /// https://no-color.org, any non-empty `NO_COLOR` turns colors off.
+    pub fn log_colors(&self) -> bool {
+        !self.no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+    }
+
+    pub fn node_id(&self) -> String {
+        self.node_id
+            .clone()
+            .unwrap_or_else(|| format!("unraid_{}", self.device_name))
+    }
+}
+
+/// Home Assistant only accepts `[a-zA-Z0-9_-]` in discovery node ids.
+fn parse_node_id(node_id: &str) -> Result<String, String> {
+    if !node_id.is_empty()
+        && node_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(node_id.to_string())
+    } else {
+        Err("may only contain letters, digits, '_' and '-'".to_string())
+    }
 }
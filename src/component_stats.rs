@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use sysinfo::Components;
+use tracing::warn;
+
+use crate::config::{ComponentSensorReporter, DeviceClass, Sensor, SensorReporterType, StateClass};
+
+/// Label filters for hardware temperature sensors, matched case-insensitively as substrings.
+/// An empty `include` includes every component.
+#[derive(Debug, Default, Clone)]
+pub struct ComponentFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl ComponentFilter {
+    pub fn matches(&self, label: &str) -> bool {
+        let label = label.to_lowercase();
+        let contains = |pattern: &String| label.contains(&pattern.to_lowercase());
+        (self.include.is_empty() || self.include.iter().any(contains))
+            && !self.exclude.iter().any(contains)
+    }
+}
+
+/// One temperature sensor per hwmon component that reports a temperature, drives (NVMe,
+/// `drivetemp`) get `disk_<label>_temp` ids.
+pub fn sensor_list(filter: &ComponentFilter) -> Vec<Sensor> {
+    let components = Components::new_with_refreshed_list();
+    let labels: Vec<&str> = components
+        .iter()
+        .filter(|component| component.temperature().is_some())
+        .map(|component| component.label())
+        .collect();
+    component_sensors(&labels)
+        .into_iter()
+        .filter(|sensor| match &sensor.reporter {
+            Some(SensorReporterType::Component(reporter)) => filter.matches(&reporter.label),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Sensors for components with these labels, in hwmon order. Components whose labels end
+/// up as the same id, e.g. two `nvme Composite` drives, get their number among them added:
+/// `disk_nvme_composite_1_temp`, `disk_nvme_composite_2_temp`.
+pub fn component_sensors(labels: &[&str]) -> Vec<Sensor> {
+    let mut sensors: Vec<Sensor> = labels
+        .iter()
+        .enumerate()
+        .map(|(position, label)| {
+            let index = labels[..position].iter().filter(|l| *l == label).count();
+            component_sensor(label, index)
+        })
+        .collect();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for sensor in &sensors {
+        *counts.entry(sensor.id.clone()).or_default() += 1;
+    }
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for sensor in sensors.iter_mut() {
+        if counts[&sensor.id] < 2 {
+            continue;
+        }
+        let number = seen.entry(sensor.id.clone()).or_default();
+        *number += 1;
+        let Some(prefix) = sensor.id.strip_suffix("_temp") else {
+            continue;
+        };
+        let id = format!("{}_{}_temp", prefix, number);
+        warn!(
+            "Sensor id {} is used by more than one component, using {}",
+            sensor.id, id
+        );
+        sensor.id = id;
+        sensor.name = format!("{} {}", sensor.name, number);
+    }
+    sensors
+}
+
+/// Label prefixes of hwmon drivers that report drive temperatures.
+const DRIVE_LABELS: &[&str] = &["nvme", "drivetemp", "sata", "ssd", "hdd"];
+
+pub fn is_drive(label: &str) -> bool {
+    let label = label.to_lowercase();
+    DRIVE_LABELS.iter().any(|prefix| label.starts_with(prefix))
+}
+
+fn component_sensor(label: &str, index: usize) -> Sensor {
+    if is_drive(label) {
+        return Sensor {
+            id: format!("disk_{}_temp", label_id(label)),
+            name: format!("Disk {} Temperature", label),
+            icon: Some("harddisk".to_string()),
+            ..component_sensor_base(label, index)
+        };
+    }
+    Sensor {
+        id: format!("component_{}_temp", label_id(label)),
+        name: format!("{} Temperature", label),
+        ..component_sensor_base(label, index)
+    }
+}
+
+fn component_sensor_base(label: &str, index: usize) -> Sensor {
+    Sensor {
+        unit: Some("°C".to_string()),
+        device_class: Some(DeviceClass::Temperature),
+        state_class: Some(StateClass::Measurement),
+        reporter: Some(SensorReporterType::Component(ComponentSensorReporter {
+            label: label.to_string(),
+            index,
+        })),
+        ..Default::default()
+    }
+}
+
+/// Lowercases the label and joins its alphanumeric runs with `_`, so ids stay stable and
+/// topic safe.
+pub fn label_id(label: &str) -> String {
+    label
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
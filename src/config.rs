@@ -1,3 +1,6 @@
+use crate::docker_stats;
+use crate::parsers::calculate_cpu_percent;
+use anyhow::Context;
 use bollard::{
     query_parameters::{
         ListContainersOptions, ListImagesOptions, ListVolumesOptions, StatsOptions,
@@ -6,41 +9,137 @@ use bollard::{
     Docker,
 };
 use futures_util::StreamExt;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde::{Deserializer, Serializer};
 use serde_json::{json, Value};
-use std::{collections::HashMap, path::PathBuf, process::Command, sync::Arc};
-use sysinfo::System;
-use tracing::instrument;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+};
+use sysinfo::{Components, System};
+use tokio::process::Command;
+use tracing::{instrument, warn};
 
+/// Loads a sensor config, picking the format from the file extension. Anything that is
+/// not `.json`, `.yaml` or `.yml` is read as toml.
 pub fn load_config(file: &PathBuf) -> Config {
-    let content = std::fs::read_to_string(file).expect("Failed to read config file");
-    toml::from_str(&content).expect("Failed to parse config file")
+    try_load_config(file).expect("Failed to load config file")
+}
+
+pub fn try_load_config(file: &PathBuf) -> anyhow::Result<Config> {
+    let content = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read config file {}", file.display()))?;
+    let config = match file.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(anyhow::Error::from),
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+        _ => toml::from_str(&content).map_err(anyhow::Error::from),
+    };
+    config.with_context(|| format!("Failed to parse config file {}", file.display()))
+}
+
+/// Loads each config in order, later files replacing sensors with the same id.
+pub fn load_configs(files: &[PathBuf]) -> Config {
+    files
+        .iter()
+        .map(load_config)
+        .fold(Config::default(), |mut config, other| {
+            config.merge(other);
+            config
+        })
 }
 
-#[derive(Serialize, Default, Deserialize, Debug)]
+pub fn try_load_configs(files: &[PathBuf]) -> anyhow::Result<Config> {
+    let mut config = Config::default();
+    for file in files {
+        config.merge(try_load_config(file)?);
+    }
+    Ok(config)
+}
+
+#[derive(Serialize, Default, Deserialize, Debug, JsonSchema)]
 pub struct Config {
+    /// Sensors whose id starts with one of these are not built at all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_prefixes: Vec<String>,
+    /// Unit memory_used and memory_total are published in, bytes by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_unit: Option<MemoryUnit>,
+    /// Disks (`sdb` or `/dev/sdb`) that get a `disk_<name>_active` spin state sensor.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spin_state_devices: Vec<String>,
+    /// lm-sensors labels tried in order for `cpu_temp`, replaces the built-in list.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu_temp_labels: Vec<String>,
+    /// Container labels published as attributes of the container sensors.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub container_labels: Vec<String>,
+    /// Icon for sensors of a device class that don't set one, replaces the built-in default.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub device_class_icons: HashMap<DeviceClass, String>,
     #[serde(deserialize_with = "deserialize_sensors")]
     pub sensors: HashMap<String, Sensors>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Config {
+    pub fn merge(&mut self, other: Config) {
+        self.disabled_prefixes.extend(other.disabled_prefixes);
+        if other.memory_unit.is_some() {
+            self.memory_unit = other.memory_unit;
+        }
+        self.spin_state_devices.extend(other.spin_state_devices);
+        if !other.cpu_temp_labels.is_empty() {
+            self.cpu_temp_labels = other.cpu_temp_labels;
+        }
+        self.container_labels.extend(other.container_labels);
+        self.device_class_icons.extend(other.device_class_icons);
+        self.sensors.extend(other.sensors);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(tag = "type")]
 pub enum Sensors {
     #[serde(rename = "override")]
     SensorOverride(SensorConfig),
     #[serde(rename = "command")]
     Command(CommandSensor),
+    #[serde(rename = "static")]
+    Static(StaticSensor),
+    #[serde(rename = "command_multi")]
+    CommandMulti(CommandMultiSensor),
+    #[serde(rename = "switch")]
+    Switch(SwitchConfig),
 }
 
-#[derive(Serialize, Default, Deserialize, Debug)]
+#[derive(Serialize, Default, Deserialize, Debug, JsonSchema)]
 pub struct SensorConfig {
     #[serde(skip_deserializing)]
     pub id: String,
     pub name: Option<String>,
     pub unit: Option<String>,
     pub device_class: Option<DeviceClass>,
+    pub state_class: Option<StateClass>,
+    pub entity_category: Option<EntityCategory>,
+    pub expire_after: Option<u32>,
+    pub suggested_display_precision: Option<u8>,
+    pub enabled_by_default: Option<bool>,
+    pub interval: Option<u32>,
+    pub retain: Option<bool>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub out_of_range: Option<OutOfRange>,
+    pub availability: Option<bool>,
+    pub critical: Option<bool>,
+    pub extra_discovery: Option<serde_json::Map<String, Value>>,
     pub icon: Option<String>,
+    /// Discover the sensor as another component, e.g. `binary_sensor` for a built-in that
+    /// reports `ON`/`OFF`.
+    pub component: Option<SensorKind>,
+    /// Replaces the command of a built-in command sensor, the output is parsed the same way.
+    pub command: Option<String>,
+    pub args: Option<Vec<String>>,
     pub disabled: bool,
 }
 
@@ -74,7 +173,7 @@ where
 
     for (id, sensor) in sensors.iter_mut() {
         match sensor {
-            SensorsDump::SensorOverride(s) => s.id = id.clone(),
+            SensorsDump::SensorOverride(s) => s.sensor.id = id.clone(),
         }
     }
 
@@ -85,35 +184,126 @@ where
 #[serde(tag = "type")]
 pub enum SensorsDump {
     #[serde(rename = "override")]
-    SensorOverride(Sensor),
+    SensorOverride(SensorDump),
 }
-#[derive(Serialize, Default, Deserialize)]
+
+/// A sensor in the dump, with the command it runs so it's clear what an override of
+/// `command`/`args` replaces.
+#[derive(Serialize, Deserialize)]
+pub struct SensorDump {
+    #[serde(flatten)]
+    pub sensor: Sensor,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+}
+
+impl From<Sensor> for SensorDump {
+    fn from(sensor: Sensor) -> Self {
+        let (command, args) = match &sensor.reporter {
+            Some(SensorReporterType::Command(reporter)) => {
+                (Some(reporter.command.clone()), reporter.args.clone())
+            }
+            _ => (None, None),
+        };
+        SensorDump {
+            sensor,
+            command,
+            args,
+        }
+    }
+}
+#[derive(Serialize, Default, Deserialize, Clone)]
 pub struct Sensor {
     #[serde(skip_deserializing, skip_serializing)]
     pub id: String,
     pub name: String,
     pub unit: Option<String>,
     pub device_class: Option<DeviceClass>,
+    pub state_class: Option<StateClass>,
+    pub entity_category: Option<EntityCategory>,
+    pub expire_after: Option<u32>,
+    pub suggested_display_precision: Option<u8>,
+    pub enabled_by_default: Option<bool>,
+    pub interval: Option<u32>,
+    pub retain: Option<bool>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub out_of_range: Option<OutOfRange>,
+    pub availability: Option<bool>,
+    pub critical: Option<bool>,
+    pub extra_discovery: Option<serde_json::Map<String, Value>>,
     pub icon: Option<String>,
     pub disabled: bool,
     #[serde(skip, default)]
+    pub kind: SensorKind,
+    /// Readings are °C and published converted to `--temp-unit`.
+    #[serde(skip, default)]
+    pub temp_unit: TempUnit,
+    #[serde(skip, default)]
     pub reporter: Option<SensorReporterType>,
 }
 
-#[derive(Serialize, Default, Deserialize, Debug)]
+/// What happens to a value outside of a sensor's `min`/`max`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRange {
+    /// Publish the nearest limit instead.
+    #[default]
+    Clamp,
+    /// Publish nothing for this reading.
+    Drop,
+}
+
+pub const BINARY_SENSOR_ON: &str = "ON";
+pub const BINARY_SENSOR_OFF: &str = "OFF";
+
+/// Home Assistant component the sensor is discovered as.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorKind {
+    #[default]
+    Sensor,
+    BinarySensor,
+}
+
+impl SensorKind {
+    pub fn component(&self) -> &'static str {
+        match self {
+            SensorKind::Sensor => "sensor",
+            SensorKind::BinarySensor => "binary_sensor",
+        }
+    }
+}
+
+#[derive(Serialize, Default, Deserialize, Debug, JsonSchema)]
 pub struct CommandSensor {
     #[serde(skip_deserializing)]
     pub id: String,
     pub name: String,
     pub unit: Option<String>,
     pub device_class: Option<DeviceClass>,
+    pub state_class: Option<StateClass>,
+    pub entity_category: Option<EntityCategory>,
+    pub expire_after: Option<u32>,
+    pub suggested_display_precision: Option<u8>,
+    pub enabled_by_default: Option<bool>,
+    pub interval: Option<u32>,
+    pub retain: Option<bool>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub out_of_range: Option<OutOfRange>,
+    pub availability: Option<bool>,
+    pub critical: Option<bool>,
+    pub extra_discovery: Option<serde_json::Map<String, Value>>,
     pub icon: Option<String>,
     pub command: String,
     pub args: Option<Vec<String>>,
     pub post_process: Option<PostProcess>,
     pub disabled: bool,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 pub enum PostProcess {
     TrimWhitespace,
     ParseFloat,
@@ -121,7 +311,122 @@ pub enum PostProcess {
     ExtractNumber,
     ToUpperCase,
     ToLowerCase,
+    DurationHuman,
+    FirstNumber,
+}
+/// A Home Assistant switch that runs `command_on`/`command_off` when it is toggled, see
+/// [`crate::switch`].
+#[derive(Serialize, Default, Deserialize, Debug, Clone, JsonSchema)]
+pub struct SwitchConfig {
+    #[serde(skip_deserializing)]
+    pub id: String,
+    pub name: String,
+    pub command_on: String,
+    pub args_on: Option<Vec<String>>,
+    pub command_off: String,
+    pub args_off: Option<Vec<String>>,
+    pub icon: Option<String>,
+    pub disabled: bool,
+}
+
+/// A sensor that always publishes the same `value`, e.g. the location of the server.
+#[derive(Serialize, Default, Deserialize, Debug, JsonSchema)]
+pub struct StaticSensor {
+    #[serde(skip_deserializing)]
+    pub id: String,
+    pub name: String,
+    pub value: String,
+    /// Published as the json attributes of the sensor.
+    pub attributes: Option<serde_json::Map<String, Value>>,
+    pub unit: Option<String>,
+    pub device_class: Option<DeviceClass>,
+    pub entity_category: Option<EntityCategory>,
+    pub enabled_by_default: Option<bool>,
+    pub retain: Option<bool>,
+    pub extra_discovery: Option<serde_json::Map<String, Value>>,
+    pub icon: Option<String>,
+    pub disabled: bool,
+}
+
+impl From<&StaticSensor> for Sensor {
+    fn from(static_sensor: &StaticSensor) -> Self {
+        Sensor {
+            id: static_sensor.id.clone(),
+            name: static_sensor.name.clone(),
+            unit: static_sensor.unit.clone(),
+            device_class: static_sensor.device_class.clone(),
+            entity_category: static_sensor.entity_category.clone(),
+            enabled_by_default: static_sensor.enabled_by_default,
+            retain: static_sensor.retain,
+            extra_discovery: static_sensor.extra_discovery.clone(),
+            icon: static_sensor.icon.clone(),
+            disabled: static_sensor.disabled,
+            reporter: Some(SensorReporterType::Static(StaticSensorReporter {
+                value: static_sensor.value.clone(),
+                attributes: static_sensor.attributes.clone().map(Value::Object),
+            })),
+            ..Default::default()
+        }
+    }
+}
+
+/// Runs one command and publishes several sensors from its output, `<id>_<child id>`.
+#[derive(Serialize, Default, Deserialize, Debug, JsonSchema)]
+pub struct CommandMultiSensor {
+    #[serde(skip_deserializing)]
+    pub id: String,
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub sensors: BTreeMap<String, CommandMultiChild>,
+    pub disabled: bool,
 }
+
+/// One value of a [`CommandMultiSensor`], the rest of the first line that starts with `line`.
+/// The other sensor settings can be set with an `override` on the full id.
+#[derive(Serialize, Default, Deserialize, Debug, JsonSchema)]
+pub struct CommandMultiChild {
+    pub name: String,
+    pub line: String,
+    pub post_process: Option<PostProcess>,
+    pub unit: Option<String>,
+    pub device_class: Option<DeviceClass>,
+    pub state_class: Option<StateClass>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+impl CommandMultiSensor {
+    /// The child sensors, all reading the same command output.
+    pub fn sensors(&self) -> Vec<Sensor> {
+        let command = Arc::new(SharedCommand {
+            command: self.command.clone(),
+            args: self.args.clone(),
+            output: tokio::sync::OnceCell::new(),
+        });
+        self.sensors
+            .iter()
+            .map(|(child_id, child)| Sensor {
+                id: format!("{}_{}", self.id, child_id),
+                name: child.name.clone(),
+                unit: child.unit.clone(),
+                device_class: child.device_class.clone(),
+                state_class: child.state_class.clone(),
+                icon: child.icon.clone(),
+                disabled: self.disabled || child.disabled,
+                reporter: Some(SensorReporterType::CommandMulti(
+                    CommandMultiSensorReporter {
+                        command: command.clone(),
+                        line: child.line.clone(),
+                        transform: post_process_transform(child.post_process.as_ref()),
+                    },
+                )),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
 impl From<&CommandSensor> for Sensor {
     fn from(command_sensor: &CommandSensor) -> Self {
         Sensor {
@@ -129,38 +434,65 @@ impl From<&CommandSensor> for Sensor {
             name: command_sensor.name.clone(),
             unit: command_sensor.unit.clone(),
             device_class: command_sensor.device_class.clone(),
+            state_class: command_sensor.state_class.clone(),
+            entity_category: command_sensor.entity_category.clone(),
+            expire_after: command_sensor.expire_after,
+            suggested_display_precision: command_sensor.suggested_display_precision,
+            enabled_by_default: command_sensor.enabled_by_default,
+            interval: command_sensor.interval,
+            retain: command_sensor.retain,
+            min: command_sensor.min,
+            max: command_sensor.max,
+            out_of_range: command_sensor.out_of_range,
+            availability: command_sensor.availability,
+            critical: command_sensor.critical,
+            extra_discovery: command_sensor.extra_discovery.clone(),
             icon: command_sensor.icon.clone(),
             disabled: command_sensor.disabled,
+            kind: SensorKind::Sensor,
+            temp_unit: TempUnit::C,
             reporter: Some(SensorReporterType::Command(CommandSensorReporter {
                 command: command_sensor.command.clone(),
                 args: command_sensor.args.clone(),
-                transform: match command_sensor.post_process {
-                    Some(PostProcess::TrimWhitespace) => {
-                        Some(Arc::new(|s| Some(s.trim().to_string())))
-                    }
-                    Some(PostProcess::ParseFloat) => {
-                        Some(Arc::new(|s| s.parse::<f64>().ok().map(|v| v.to_string())))
-                    }
-                    Some(PostProcess::ParseInteger) => {
-                        Some(Arc::new(|s| s.parse::<i64>().ok().map(|v| v.to_string())))
-                    }
-                    Some(PostProcess::ExtractNumber) => Some(Arc::new(|s| {
-                        s.chars()
-                            .filter(|c| c.is_numeric())
-                            .collect::<String>()
-                            .parse::<f64>()
-                            .ok()
-                            .map(|v| v.to_string())
-                    })),
-                    Some(PostProcess::ToUpperCase) => Some(Arc::new(|s| Some(s.to_uppercase()))),
-                    Some(PostProcess::ToLowerCase) => Some(Arc::new(|s| Some(s.to_lowercase()))),
-                    None => Some(Arc::new(|s| Some(s.to_string()))),
-                },
+                transform: Some(post_process_transform(command_sensor.post_process.as_ref())),
+                ..Default::default()
             })),
         }
     }
 }
 
+/// The function a `post_process` option applies to the trimmed command output.
+pub fn post_process_transform(post_process: Option<&PostProcess>) -> SensorTransform {
+    match post_process {
+        Some(PostProcess::TrimWhitespace) => Arc::new(|s| Some(s.trim().to_string())),
+        Some(PostProcess::ParseFloat) => Arc::new(|s| s.parse::<f64>().ok().map(|v| v.to_string())),
+        Some(PostProcess::ParseInteger) => {
+            Arc::new(|s| s.parse::<i64>().ok().map(|v| v.to_string()))
+        }
+        Some(PostProcess::ExtractNumber) => Arc::new(|s| {
+            s.chars()
+                .filter(|c| c.is_numeric())
+                .collect::<String>()
+                .parse::<f64>()
+                .ok()
+                .map(|v| v.to_string())
+        }),
+        Some(PostProcess::ToUpperCase) => Arc::new(|s| Some(s.to_uppercase())),
+        Some(PostProcess::ToLowerCase) => Arc::new(|s| Some(s.to_lowercase())),
+        Some(PostProcess::DurationHuman) => {
+            Arc::new(|s| s.trim().parse::<u64>().ok().map(format_duration_human))
+        }
+        Some(PostProcess::FirstNumber) => Arc::new(|s| first_number(s).map(|v| v.to_string())),
+        None => Arc::new(|s| Some(s.to_string())),
+    }
+}
+
+/// First number in the text, e.g. `51.3` from `+51.3°C  (high = +80.0°C)`.
+pub fn first_number(text: &str) -> Option<f64> {
+    text.split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .find_map(|word| word.parse::<f64>().ok())
+}
+
 fn deserialize_sensors<'de, D>(deserializer: D) -> Result<HashMap<String, Sensors>, D::Error>
 where
     D: Deserializer<'de>,
@@ -171,6 +503,9 @@ where
         match sensor {
             Sensors::SensorOverride(s) => s.id = id.clone(),
             Sensors::Command(s) => s.id = id.clone(),
+            Sensors::Static(s) => s.id = id.clone(),
+            Sensors::CommandMulti(s) => s.id = id.clone(),
+            Sensors::Switch(s) => s.id = id.clone(),
         }
     }
 
@@ -191,60 +526,408 @@ impl Sensor {
         if other.device_class.is_some() {
             self.device_class = other.device_class.clone();
         }
+        if other.state_class.is_some() {
+            self.state_class = other.state_class.clone();
+        }
+        if other.entity_category.is_some() {
+            self.entity_category = other.entity_category.clone();
+        }
+        if other.expire_after.is_some() {
+            self.expire_after = other.expire_after;
+        }
+        if other.suggested_display_precision.is_some() {
+            self.suggested_display_precision = other.suggested_display_precision;
+        }
+        if other.enabled_by_default.is_some() {
+            self.enabled_by_default = other.enabled_by_default;
+        }
+        if other.interval.is_some() {
+            self.interval = other.interval;
+        }
+        if other.retain.is_some() {
+            self.retain = other.retain;
+        }
+        if other.min.is_some() {
+            self.min = other.min;
+        }
+        if other.max.is_some() {
+            self.max = other.max;
+        }
+        if other.out_of_range.is_some() {
+            self.out_of_range = other.out_of_range;
+        }
+        if other.availability.is_some() {
+            self.availability = other.availability;
+        }
+        if other.critical.is_some() {
+            self.critical = other.critical;
+        }
+        if other.extra_discovery.is_some() {
+            self.extra_discovery = other.extra_discovery.clone();
+        }
         if other.icon.is_some() {
             self.icon = other.icon.clone();
         }
+        if let Some(component) = &other.component {
+            self.kind = component.clone();
+        }
+        if other.command.is_some() || other.args.is_some() {
+            match &mut self.reporter {
+                Some(SensorReporterType::Command(reporter)) => {
+                    if let Some(command) = &other.command {
+                        reporter.command = command.clone();
+                    }
+                    if other.args.is_some() {
+                        reporter.args = other.args.clone();
+                    }
+                }
+                _ => warn!(
+                    "sensor {} does not run a command, ignoring command/args override",
+                    self.id
+                ),
+            }
+        }
         if other.disabled {
             self.disabled = other.disabled;
         }
     }
 
+    /// A critical sensor without a value makes a one-shot run exit non-zero.
+    pub fn is_critical(&self) -> bool {
+        self.critical.unwrap_or(false)
+    }
+
+    /// Applies `min`/`max` to a numeric reading, keeping its number of decimals when
+    /// clamping. Anything that isn't a number is passed through.
+    pub fn apply_limits(&self, value: String) -> Option<String> {
+        let Ok(number) = value.parse::<f64>() else {
+            return Some(value);
+        };
+        let limited = number
+            .max(self.min.unwrap_or(f64::NEG_INFINITY))
+            .min(self.max.unwrap_or(f64::INFINITY));
+        if limited == number {
+            return Some(value);
+        }
+        match self.out_of_range.unwrap_or_default() {
+            OutOfRange::Clamp => {
+                let decimals = value
+                    .split_once('.')
+                    .map_or(0, |(_, decimals)| decimals.len());
+                Some(format!("{:.*}", decimals, limited))
+            }
+            OutOfRange::Drop => {
+                warn!(
+                    "Sensor {} value {} is out of range, dropping it",
+                    self.id, value
+                );
+                None
+            }
+        }
+    }
+
+    /// The id as used in topics and `unique_id`, see [`sanitize_id`].
+    pub fn object_id(&self) -> String {
+        sanitize_id(&self.id)
+    }
+
+    /// Describes why the unit doesn't fit the device class, Home Assistant ignores such
+    /// entities without telling anyone.
+    pub fn unit_mismatch(&self) -> Option<String> {
+        let device_class = self.device_class.as_ref()?;
+        let units = device_class.units()?;
+        match &self.unit {
+            Some(unit) if units.is_empty() => Some(format!(
+                "device_class {} takes no unit, got {}",
+                device_class.as_str(),
+                unit
+            )),
+            Some(unit) if !units.contains(&unit.as_str()) => Some(format!(
+                "unit {} is not valid for device_class {}, expected one of {}",
+                unit,
+                device_class.as_str(),
+                units.join(", ")
+            )),
+            None if !units.is_empty() => Some(format!(
+                "device_class {} needs a unit, one of {}",
+                device_class.as_str(),
+                units.join(", ")
+            )),
+            _ => None,
+        }
+    }
+
     pub fn sensor_topic(&self, node_id: &str) -> String {
-        format!("{}/sensor/{}/state", node_id, self.id)
+        format!(
+            "{}/{}/{}/state",
+            node_id,
+            self.kind.component(),
+            self.object_id()
+        )
+    }
+    /// Only used when the sensor has `availability` set, see [`Sensor::has_own_availability`].
+    pub fn availability_topic(&self, node_id: &str) -> String {
+        format!(
+            "{}/{}/{}/availability",
+            node_id,
+            self.kind.component(),
+            self.object_id()
+        )
+    }
+
+    /// Sensors that can fail on their own (e.g. a spun down disk) publish their own
+    /// online/offline instead of only using the device availability.
+    pub fn has_own_availability(&self) -> bool {
+        self.availability.unwrap_or(false)
+    }
+
+    pub fn attributes_topic(&self, node_id: &str) -> String {
+        format!(
+            "{}/{}/{}/attributes",
+            node_id,
+            self.kind.component(),
+            self.object_id()
+        )
     }
     pub fn discovery_topic(&self, discovery_prefix: &str, node_id: &str) -> String {
-        format!("{}/sensor/{}/{}/config", discovery_prefix, node_id, self.id)
+        format!(
+            "{}/{}/{}/{}/config",
+            discovery_prefix,
+            self.kind.component(),
+            node_id,
+            self.object_id()
+        )
     }
-    pub fn disovery_config(&self, device_name: &str, node_id: &str, device_info: &Value) -> Value {
+    /// With `has_entity_name` Home Assistant prefixes the device name itself, so the name
+    /// is just the measurement.
+    pub fn disovery_config(&self, node_id: &str, device_info: &Value) -> Value {
         let mut config = json!({
-            "name": format!("{} {}", device_name, self.name),
+            "name": self.name,
+            "has_entity_name": true,
             "state_topic": self.sensor_topic(node_id),
-            "unique_id": format!("{}_{}", node_id, self.id),
+            "unique_id": format!("{}_{}", node_id, self.object_id()),
+            // keeps entity ids stable when the name is changed
+            "object_id": format!("{}_{}", entity_id_prefix(node_id), self.object_id()),
             "device": device_info,
-            "unit_of_measurement": self.unit,
+            "origin": {
+                "name": env!("CARGO_PKG_NAME"),
+                "sw_version": env!("CARGO_PKG_VERSION"),
+            },
         });
 
+        match self.kind {
+            SensorKind::Sensor => {
+                if let Some(unit) = &self.unit {
+                    config["unit_of_measurement"] = json!(unit);
+                }
+            }
+            SensorKind::BinarySensor => {
+                config["payload_on"] = json!(BINARY_SENSOR_ON);
+                config["payload_off"] = json!(BINARY_SENSOR_OFF);
+            }
+        }
+
         if let Some(device_class) = &self.device_class {
             config["device_class"] = json!(device_class);
         }
+        if let Some(state_class) = &self.state_class {
+            config["state_class"] = json!(state_class);
+        }
+        if let Some(entity_category) = &self.entity_category {
+            config["entity_category"] = json!(entity_category);
+        }
+        if let Some(expire_after) = self.expire_after {
+            config["expire_after"] = json!(expire_after);
+        }
+        if let Some(suggested_display_precision) = self.suggested_display_precision {
+            config["suggested_display_precision"] = json!(suggested_display_precision);
+        }
+        if let Some(enabled_by_default) = self.enabled_by_default {
+            config["enabled_by_default"] = json!(enabled_by_default);
+        }
+        if self.reporter.as_ref().is_some_and(|r| r.has_attributes()) {
+            config["json_attributes_topic"] = json!(self.attributes_topic(node_id));
+        }
         if let Some(icon_str) = &self.icon {
-            config["icon"] = json!(format!("mdi:{}", icon_str));
+            config["icon"] = json!(icon_name(icon_str));
+        }
+        // escape hatch for discovery options that aren't modelled, these win over ours
+        if let Some(extra) = &self.extra_discovery {
+            for (key, value) in extra {
+                config[key] = value.clone();
+            }
         }
 
         config
     }
 }
 
+#[derive(Clone)]
 pub enum SensorReporterType {
     System(SystemSensorReporter),
     Command(CommandSensorReporter),
+    CommandMulti(CommandMultiSensorReporter),
+    File(FileSensorReporter),
+    FileRate(FileRateReporter),
     DockerContainer(DockerContainerSensorReporter),
     Docker(DockerSensorReporter),
+    Component(ComponentSensorReporter),
+    Static(StaticSensorReporter),
+    /// Filled in by `UnraidStats::publish_stats` after the other sensors are collected.
+    Collection(CollectionStat),
 }
 impl SensorReporterType {
     pub async fn get_value(&mut self) -> Option<String> {
         match self {
             SensorReporterType::System(reporter) => reporter.get_value().await,
             SensorReporterType::Command(reporter) => reporter.get_value().await,
+            SensorReporterType::CommandMulti(reporter) => reporter.get_value().await,
+            SensorReporterType::File(reporter) => reporter.get_value().await,
+            SensorReporterType::FileRate(reporter) => reporter.get_value().await,
             SensorReporterType::DockerContainer(reporter) => reporter.get_value().await,
             SensorReporterType::Docker(reporter) => reporter.get_value().await,
+            SensorReporterType::Component(reporter) => reporter.get_value(),
+            SensorReporterType::Static(reporter) => Some(reporter.value.clone()),
+            SensorReporterType::Collection(_) => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SensorReporterType::System(_) => "system",
+            SensorReporterType::Command(_) => "command",
+            SensorReporterType::CommandMulti(_) => "command_multi",
+            SensorReporterType::File(_) => "file",
+            SensorReporterType::FileRate(_) => "file_rate",
+            SensorReporterType::DockerContainer(_) => "docker_container",
+            SensorReporterType::Docker(_) => "docker",
+            SensorReporterType::Component(_) => "component",
+            SensorReporterType::Static(_) => "static",
+            SensorReporterType::Collection(_) => "collection",
+        }
+    }
+
+    pub fn has_attributes(&self) -> bool {
+        match self {
+            SensorReporterType::Command(reporter) => reporter.attributes.is_some(),
+            SensorReporterType::Static(reporter) => reporter.attributes.is_some(),
+            SensorReporterType::DockerContainer(reporter) => reporter.labels.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Attributes gathered by the last `get_value` call.
+    pub fn get_attributes(&self) -> Option<Value> {
+        match self {
+            SensorReporterType::Command(reporter) => reporter.last_attributes.clone(),
+            SensorReporterType::Static(reporter) => reporter.attributes.clone(),
+            SensorReporterType::DockerContainer(reporter) => reporter.labels.clone(),
+            _ => None,
         }
     }
 }
+/// Always returns the same value, has no side effects.
+#[derive(Clone)]
+pub struct StaticSensorReporter {
+    pub value: String,
+    pub attributes: Option<Value>,
+}
+
+pub type SensorTransform = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+pub type AttributesTransform = Arc<dyn Fn(&str) -> Option<Value> + Send + Sync>;
+
+#[derive(Default, Clone)]
 pub struct CommandSensorReporter {
     pub command: String,
     pub args: Option<Vec<String>>,
-    pub transform: Option<Arc<dyn Fn(&str) -> Option<String> + Send + Sync>>,
+    pub transform: Option<SensorTransform>,
+    /// Builds the json attributes published alongside the state from the same command output.
+    pub attributes: Option<AttributesTransform>,
+    pub last_attributes: Option<Value>,
+}
+
+/// Reads a file like `/proc/pressure/memory` without a subprocess, the content goes
+/// through `transform`.
+#[derive(Clone)]
+pub struct FileSensorReporter {
+    pub path: PathBuf,
+    pub transform: SensorTransform,
+}
+
+impl FileSensorReporter {
+    #[instrument(level = "trace", skip(self))]
+    async fn get_value(&self) -> Option<String> {
+        (self.transform)(&tokio::fs::read_to_string(&self.path).await.ok()?)
+    }
+}
+
+pub type CounterTransform = Arc<dyn Fn(&str) -> Option<u64> + Send + Sync>;
+
+/// Per second rate of a counter in a file like `/proc/vmstat`, the file is read twice
+/// `sample` apart.
+#[derive(Clone)]
+pub struct FileRateReporter {
+    pub path: PathBuf,
+    pub sample: std::time::Duration,
+    pub counter: CounterTransform,
+}
+
+impl FileRateReporter {
+    #[instrument(level = "trace", skip(self))]
+    async fn get_value(&self) -> Option<String> {
+        let first = (self.counter)(&tokio::fs::read_to_string(&self.path).await.ok()?)?;
+        tokio::time::sleep(self.sample).await;
+        let second = (self.counter)(&tokio::fs::read_to_string(&self.path).await.ok()?)?;
+        let rate = second.saturating_sub(first) as f64 / self.sample.as_secs_f64();
+        Some(format!("{:.1}", rate))
+    }
+}
+
+/// A command whose output is shared by the sensors built from one [`CommandMultiSensor`],
+/// it runs once for all of them.
+pub struct SharedCommand {
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub output: tokio::sync::OnceCell<Option<String>>,
+}
+
+impl SharedCommand {
+    async fn output(&self) -> Option<&str> {
+        self.output
+            .get_or_init(|| async {
+                let mut command = Command::new(&self.command);
+                if let Some(args) = &self.args {
+                    command.args(args);
+                }
+                command
+                    .output()
+                    .await
+                    .ok()
+                    .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+            })
+            .await
+            .as_deref()
+    }
+}
+
+#[derive(Clone)]
+pub struct CommandMultiSensorReporter {
+    pub command: Arc<SharedCommand>,
+    pub line: String,
+    pub transform: SensorTransform,
+}
+
+impl CommandMultiSensorReporter {
+    #[instrument(level = "trace", skip(self))]
+    async fn get_value(&mut self) -> Option<String> {
+        let output = self.command.output().await?;
+        let rest = output
+            .lines()
+            .map(str::trim_start)
+            .find_map(|line| line.strip_prefix(self.line.as_str()))?;
+        (self.transform)(rest.trim())
+    }
 }
 
 impl CommandSensorReporter {
@@ -254,9 +937,12 @@ impl CommandSensorReporter {
         if let Some(args) = &self.args {
             command.args(args);
         }
-        if let Ok(output) = command.output() {
+        if let Ok(output) = command.output().await {
             let sensors_output = String::from_utf8_lossy(&output.stdout);
             let result = sensors_output.trim();
+            if let Some(attributes_fn) = &self.attributes {
+                self.last_attributes = attributes_fn(result);
+            }
             if let Some(transform_fn) = &self.transform {
                 transform_fn(result)
             } else {
@@ -267,13 +953,157 @@ impl CommandSensorReporter {
         }
     }
 }
+/// Icons without a prefix are Material Design Icons, `mdi:` is added to them. Icons that
+/// already have one (`mdi:docker`, `hass:cpu`) are used as is.
+pub fn icon_name(icon: &str) -> String {
+    if icon.contains(':') {
+        icon.to_string()
+    } else {
+        format!("mdi:{}", icon)
+    }
+}
+
+/// Lowercases the id and replaces anything but `[a-z0-9_-]` with `_`, container names can
+/// contain `.` or other characters that make questionable topics and unique ids.
+pub fn sanitize_id(id: &str) -> String {
+    id.chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '_' | '-') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Start of the entity ids of a node. The default node id of a device named `unraid` is
+/// `unraid_unraid`, its entities become `sensor.unraid_cpu_usage` instead of
+/// `sensor.unraid_unraid_cpu_usage`. Other node ids are used as is.
+pub fn entity_id_prefix(node_id: &str) -> &str {
+    match node_id.strip_prefix("unraid_") {
+        Some(rest) if rest.starts_with("unraid") => rest,
+        _ => node_id,
+    }
+}
+
+/// Formats seconds as `Xd Yh Zm`.
+pub fn format_duration_human(seconds: u64) -> String {
+    let days = seconds / 86400;
+    let hours = seconds % 86400 / 3600;
+    let minutes = seconds % 3600 / 60;
+    format!("{}d {}h {}m", days, hours, minutes)
+}
+
+#[derive(Clone)]
+pub enum CollectionStat {
+    DurationMs,
+    SensorsTotal,
+    SensorsFailed,
+    /// When the cycle was published, RFC3339.
+    LastSeen,
+}
+
+/// Temperature of a hardware sensor (hwmon), looked up by its label on every read.
+#[derive(Clone)]
+pub struct ComponentSensorReporter {
+    pub label: String,
+    /// Which of the components with this label, in hwmon order.
+    pub index: usize,
+}
+
+impl ComponentSensorReporter {
+    #[instrument(
+        level = "trace",
+        skip(self),
+        name = "ComponentSensorReporter::get_value"
+    )]
+    fn get_value(&self) -> Option<String> {
+        Components::new_with_refreshed_list()
+            .iter()
+            .filter(|component| component.label() == self.label)
+            .nth(self.index)
+            .and_then(|component| component.temperature())
+            .map(|temp| format!("{:.1}", temp))
+    }
+}
+
+/// Unit temperature sensors are published in, readings are always °C.
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum TempUnit {
+    #[default]
+    C,
+    F,
+}
+
+impl TempUnit {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            TempUnit::C => "°C",
+            TempUnit::F => "°F",
+        }
+    }
+
+    /// Converts a °C reading, keeping its number of decimals. Anything that isn't a number
+    /// is passed through.
+    pub fn convert(&self, celsius: String) -> String {
+        let Ok(number) = celsius.parse::<f64>() else {
+            return celsius;
+        };
+        match self {
+            TempUnit::C => celsius,
+            TempUnit::F => {
+                let decimals = celsius
+                    .split_once('.')
+                    .map_or(0, |(_, decimals)| decimals.len());
+                format!("{:.*}", decimals, number * 9.0 / 5.0 + 32.0)
+            }
+        }
+    }
+}
+
+/// Unit byte values of the memory sensors are divided into.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, JsonSchema)]
+pub enum MemoryUnit {
+    #[default]
+    B,
+    /// 1024³ bytes
+    GiB,
+    /// 1000³ bytes
+    GB,
+}
+
+impl MemoryUnit {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            MemoryUnit::B => "B",
+            MemoryUnit::GiB => "GiB",
+            MemoryUnit::GB => "GB",
+        }
+    }
+
+    pub fn format(&self, bytes: u64) -> String {
+        match self {
+            MemoryUnit::B => bytes.to_string(),
+            MemoryUnit::GiB => format!("{:.2}", bytes as f64 / (1u64 << 30) as f64),
+            MemoryUnit::GB => format!("{:.2}", bytes as f64 / 1e9),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum SystemSensorReporterStat {
-    MemoryUsage,
-    MemoryUsed,
-    MemoryTotal,
-    CpuUsage,
+    /// Percent with this many decimals.
+    MemoryUsage(usize),
+    MemoryUsed(MemoryUnit),
+    MemoryTotal(MemoryUnit),
+    /// Percent with this many decimals.
+    CpuUsage(usize),
     Uptime,
+    UptimeHuman,
+    ProcessCount,
+    ThreadCount,
+    CpuFrequency,
+    BootTime,
 }
+#[derive(Clone)]
 pub struct SystemSensorReporter {
     pub system: Arc<System>,
     pub name: SystemSensorReporterStat,
@@ -283,33 +1113,76 @@ impl SystemSensorReporter {
     #[instrument(level = "trace", skip(self), name = "SystemSesnsorReporter::get_value")]
     async fn get_value(&self) -> Option<String> {
         match self.name {
-            SystemSensorReporterStat::MemoryUsage => {
+            SystemSensorReporterStat::MemoryUsage(precision) => {
                 let total_memory = self.system.total_memory() as f64;
                 let used_memory = self.system.used_memory() as f64;
-                Some(format!("{:.1}", (used_memory / total_memory) * 100.0))
+                Some(format!(
+                    "{:.*}",
+                    precision,
+                    (used_memory / total_memory) * 100.0
+                ))
             }
-            SystemSensorReporterStat::MemoryUsed => {
-                Some(format!("{:.1}", self.system.used_memory()))
+            SystemSensorReporterStat::MemoryUsed(unit) => {
+                Some(unit.format(self.system.used_memory()))
             }
-            SystemSensorReporterStat::MemoryTotal => {
-                Some(format!("{:.1}", self.system.total_memory()))
+            SystemSensorReporterStat::MemoryTotal(unit) => {
+                Some(unit.format(self.system.total_memory()))
             }
-            SystemSensorReporterStat::CpuUsage => {
+            SystemSensorReporterStat::CpuUsage(precision) => {
                 let cpu_usage = self.system.global_cpu_usage();
-                Some(format!("{:.1}", cpu_usage))
+                Some(format!("{:.*}", precision, cpu_usage))
             }
             SystemSensorReporterStat::Uptime => Some(format!("{}", System::uptime())),
+            SystemSensorReporterStat::UptimeHuman => Some(format_duration_human(System::uptime())),
+            // timestamp sensors need RFC3339 with a timezone
+            SystemSensorReporterStat::BootTime => {
+                chrono::DateTime::from_timestamp(System::boot_time() as i64, 0)
+                    .map(|boot_time| boot_time.to_rfc3339())
+            }
+            SystemSensorReporterStat::CpuFrequency => {
+                let cpus = self.system.cpus();
+                if cpus.is_empty() {
+                    return None;
+                }
+                let total: u64 = cpus.iter().map(|cpu| cpu.frequency()).sum();
+                Some(format!("{}", total / cpus.len() as u64))
+            }
+            // on Linux threads are listed alongside processes, only count the processes
+            SystemSensorReporterStat::ProcessCount => Some(
+                self.system
+                    .processes()
+                    .values()
+                    .filter(|process| process.thread_kind().is_none())
+                    .count()
+                    .to_string(),
+            ),
+            SystemSensorReporterStat::ThreadCount => Some(
+                self.system
+                    .processes()
+                    .values()
+                    .filter(|process| process.thread_kind().is_none())
+                    .map(|process| process.tasks().map_or(1, |tasks| tasks.len().max(1)))
+                    .sum::<usize>()
+                    .to_string(),
+            ),
         }
     }
 }
 
+#[derive(Clone)]
 pub enum DockerSensorReporterStat {
     ImagesCount,
     ImagesSize,
     VolumesCount,
     RunningCount,
     UnhealthyCount,
+    StoppedCount,
+    PausedCount,
+    TotalCount,
+    ReclaimableSize,
+    DanglingImagesCount,
 }
+#[derive(Clone)]
 pub struct DockerSensorReporter {
     pub docker: Arc<Docker>,
     pub stat: DockerSensorReporterStat,
@@ -355,7 +1228,7 @@ impl DockerSensorReporter {
                 let filter = ListVolumesOptions { filters: None };
                 let volumes = self.docker.list_volumes(Some(filter)).await;
                 volumes
-                    .and_then(|volumes| Ok(volumes.volumes.as_ref().map(|v| v.len().to_string())))
+                    .map(|volumes| volumes.volumes.as_ref().map(|v| v.len().to_string()))
                     .ok()
                     .flatten()
             }
@@ -369,16 +1242,51 @@ impl DockerSensorReporter {
                 filters.insert("health".into(), vec!["unhealthy".into()]);
                 list_containers(&self.docker, filters).await
             }
+            DockerSensorReporterStat::StoppedCount => {
+                let mut filters = HashMap::new();
+                filters.insert("status".into(), vec!["exited".into()]);
+                list_containers(&self.docker, filters).await
+            }
+            DockerSensorReporterStat::PausedCount => {
+                let mut filters = HashMap::new();
+                filters.insert("status".into(), vec!["paused".into()]);
+                list_containers(&self.docker, filters).await
+            }
+            DockerSensorReporterStat::TotalCount => {
+                list_containers(&self.docker, HashMap::new()).await
+            }
+            DockerSensorReporterStat::DanglingImagesCount => {
+                let mut filters = HashMap::new();
+                filters.insert("dangling".into(), vec!["true".into()]);
+                self.docker
+                    .list_images(Some(ListImagesOptions {
+                        filters: Some(filters),
+                        ..Default::default()
+                    }))
+                    .await
+                    .map(|images| images.len().to_string())
+                    .ok()
+            }
+            DockerSensorReporterStat::ReclaimableSize => self
+                .docker
+                .df(None)
+                .await
+                .map(|usage| docker_stats::reclaimable_size(&usage).to_string())
+                .ok(),
         }
     }
 }
+#[derive(Clone)]
 pub enum DockerContainerSensorReporterStat {
     CpuUsage,
     MemoryUsage,
     Status,
 }
+#[derive(Clone)]
 pub struct DockerContainerSensorReporter {
     pub container: Arc<ContainerSummary>,
+    /// The configured `container_labels` of this container, published as attributes.
+    pub labels: Option<Value>,
     pub docker: Arc<Docker>,
     pub stats_stash: Arc<tokio::sync::Mutex<Option<ContainerStatsResponse>>>,
     pub stat: DockerContainerSensorReporterStat,
@@ -391,42 +1299,57 @@ impl DockerContainerSensorReporter {
         name = "DockerContainerSesnsorReporter::get_value"
     )]
     async fn get_value(&self) -> Option<String> {
-        if self.stats_stash.lock().await.is_none() {
+        if matches!(self.stat, DockerContainerSensorReporterStat::Status) {
+            return self.container.status.clone();
+        }
+        // the lock is held while fetching, the other sensors of this container wait for
+        // this call instead of opening their own stats streams
+        let mut stats_stash = self.stats_stash.lock().await;
+        if stats_stash.is_none() {
+            let _permit = docker_stats::STATS_STREAMS.acquire().await.ok()?;
             let mut stats_stream = self.docker.stats(
-                &self.container.id.as_ref().unwrap(),
+                self.container.id.as_ref().unwrap(),
                 Some(StatsOptions {
                     stream: true,
                     one_shot: false,
                 }),
             );
             if let Some(Ok(stats)) = stats_stream.next().await {
-                self.stats_stash.lock().await.replace(stats.clone());
+                stats_stash.replace(stats);
             }
         }
-        if let Some(stats) = self.stats_stash.lock().await.clone() {
-            match self.stat {
-                DockerContainerSensorReporterStat::CpuUsage => {
-                    let cpu_percent = calculate_cpu_percent(&stats);
-                    Some(format!("{}", cpu_percent))
-                }
-                DockerContainerSensorReporterStat::MemoryUsage => stats
-                    .memory_stats
-                    .map(|m| m.usage)
-                    .flatten()
-                    .map(|memory_usage| format!("{}", memory_usage)),
-                DockerContainerSensorReporterStat::Status => {
-                    self.container.status.as_ref().map(|status| status.clone())
-                }
+        let stats = stats_stash.as_ref()?;
+        match self.stat {
+            DockerContainerSensorReporterStat::CpuUsage => {
+                let cpu_percent = calculate_cpu_percent(stats);
+                Some(format!("{}", cpu_percent))
             }
-        } else {
-            None
+            DockerContainerSensorReporterStat::MemoryUsage => stats
+                .memory_stats
+                .as_ref()
+                .and_then(|m| m.usage)
+                .map(|memory_usage| format!("{}", memory_usage)),
+            DockerContainerSensorReporterStat::Status => self.container.status.clone(),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum DeviceClass {
+/// Declares [`DeviceClass`] and [`DeviceClass::ALL`] from one list of variants.
+macro_rules! device_classes {
+    ($($variant:ident,)*) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+        #[serde(rename_all = "snake_case")]
+        pub enum DeviceClass {
+            $($variant,)*
+        }
+
+        impl DeviceClass {
+            pub const ALL: &'static [DeviceClass] = &[$(DeviceClass::$variant,)*];
+        }
+    };
+}
+
+device_classes! {
     Date,
     Enum,
     Timestamp,
@@ -486,36 +1409,123 @@ pub enum DeviceClass {
     WindSpeed,
 }
 
-// https://github.com/home-assistant/core/blob/dev/homeassistant/const.py#L619
-// mimic https://github.com/docker/cli/blob/4debf411d1e6efbd9ce65e4250718e9c529a6525/cli/command/container/stats_helpers.go#L166
-pub fn calculate_cpu_percent(stats: &ContainerStatsResponse) -> f64 {
-    let cpu_stats = &stats.cpu_stats;
-    let precpu_stats = &stats.precpu_stats;
-
-    let cpu_delta = cpu_stats
-        .as_ref()
-        .and_then(|c| c.cpu_usage.as_ref().and_then(|c| c.total_usage))
-        .unwrap_or_default()
-        - precpu_stats
-            .as_ref()
-            .and_then(|c| c.cpu_usage.as_ref().and_then(|c| c.total_usage))
-            .unwrap_or_default();
-    let system_delta = cpu_stats
-        .as_ref()
-        .and_then(|c| c.system_cpu_usage)
-        .unwrap_or_default()
-        - precpu_stats
-            .as_ref()
-            .and_then(|c| c.system_cpu_usage)
-            .unwrap_or_default();
-
-    if system_delta > 0 && cpu_delta > 0 {
-        let cpu_count = cpu_stats
-            .as_ref()
-            .and_then(|c| c.online_cpus)
-            .unwrap_or_default() as f64;
-        (cpu_delta as f64 / system_delta as f64) * cpu_count * 100.0
-    } else {
-        0.0
+impl DeviceClass {
+    /// Name as Home Assistant expects it, e.g. `data_size`.
+    pub fn as_str(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    /// mdi icon for sensors of this class that don't set one.
+    pub fn default_icon(&self) -> Option<&'static str> {
+        let icon = match self {
+            DeviceClass::Date | DeviceClass::Timestamp => "calendar-clock",
+            DeviceClass::Battery => "battery",
+            DeviceClass::Current => "current-ac",
+            DeviceClass::DataRate => "speedometer",
+            DeviceClass::DataSize => "harddisk",
+            DeviceClass::Duration => "timer-outline",
+            DeviceClass::Energy => "lightning-bolt",
+            DeviceClass::Frequency => "sine-wave",
+            DeviceClass::Humidity => "water-percent",
+            DeviceClass::Power => "flash",
+            DeviceClass::Speed => "speedometer",
+            DeviceClass::Temperature => "thermometer",
+            DeviceClass::Voltage => "sine-wave",
+            _ => return None,
+        };
+        Some(icon)
     }
+
+    /// Units Home Assistant accepts for the common classes, `None` when not checked.
+    /// An empty list means the class takes no unit.
+    pub fn units(&self) -> Option<&'static [&'static str]> {
+        let units: &[&str] = match self {
+            DeviceClass::Date | DeviceClass::Enum | DeviceClass::Timestamp => &[],
+            DeviceClass::Battery | DeviceClass::Humidity | DeviceClass::Moisture => &["%"],
+            DeviceClass::Current => &["A", "mA"],
+            DeviceClass::DataRate => &[
+                "bit/s", "kbit/s", "Mbit/s", "Gbit/s", "B/s", "kB/s", "MB/s", "GB/s", "KiB/s",
+                "MiB/s", "GiB/s",
+            ],
+            DeviceClass::DataSize => &[
+                "bit", "kbit", "Mbit", "Gbit", "B", "kB", "MB", "GB", "TB", "PB", "EB", "ZB", "YB",
+                "KiB", "MiB", "GiB", "TiB", "PiB", "EiB", "ZiB", "YiB",
+            ],
+            DeviceClass::Duration => &["d", "h", "min", "s", "ms", "µs"],
+            DeviceClass::Energy => &["J", "kJ", "MJ", "GJ", "mWh", "Wh", "kWh", "MWh", "GWh"],
+            DeviceClass::Frequency => &["Hz", "kHz", "MHz", "GHz"],
+            DeviceClass::Power => &["mW", "W", "kW", "MW", "GW", "TW"],
+            DeviceClass::Temperature => &["°C", "°F", "K"],
+            DeviceClass::Voltage => &["µV", "mV", "V", "kV", "MV"],
+            _ => return None,
+        };
+        Some(units)
+    }
+}
+
+impl std::str::FromStr for DeviceClass {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(device_class) = DeviceClass::ALL.iter().find(|d| d.as_str() == value) {
+            return Ok(device_class.clone());
+        }
+
+        let names = DeviceClass::ALL
+            .iter()
+            .map(|d| d.as_str())
+            .collect::<Vec<String>>();
+        let mut closest = names
+            .iter()
+            .map(|name| (strsim::levenshtein(value, name), name))
+            .filter(|(distance, _)| *distance <= 3)
+            .collect::<Vec<_>>();
+        closest.sort();
+        let suggestion = if closest.is_empty() {
+            String::new()
+        } else {
+            let closest = closest
+                .iter()
+                .take(3)
+                .map(|(_, name)| format!("`{}`", name))
+                .collect::<Vec<String>>();
+            format!(" did you mean {}?", closest.join(" or "))
+        };
+        Err(format!(
+            "unknown device_class `{}`.{} Valid device classes: {}",
+            value,
+            suggestion,
+            names.join(", ")
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for DeviceClass {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// https://developers.home-assistant.io/docs/core/entity/sensor/#available-state-classes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StateClass {
+    Measurement,
+    Total,
+    TotalIncreasing,
+}
+
+// https://developers.home-assistant.io/docs/core/entity/#generic-properties
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityCategory {
+    Config,
+    Diagnostic,
 }
@@ -1,14 +1,49 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
 use anyhow::Result;
-use bollard::{query_parameters::ListContainersOptions, secret::ContainerSummary, Docker};
-use tokio::sync::Mutex;
+use bollard::{
+    query_parameters::ListContainersOptions,
+    secret::{ContainerSummary, ContainerSummaryStateEnum, SystemDataUsageResponse},
+    Docker, API_DEFAULT_VERSION,
+};
+use serde_json::json;
+use tokio::sync::{Mutex, Semaphore};
+use tracing::warn;
 
 use crate::config::{
-    DeviceClass, DockerContainerSensorReporter, DockerContainerSensorReporterStat,
-    DockerSensorReporter, DockerSensorReporterStat, Sensor, SensorReporterType,
+    self, DeviceClass, DockerContainerSensorReporter, DockerContainerSensorReporterStat,
+    DockerSensorReporter, DockerSensorReporterStat, EntityCategory, Sensor, SensorReporterType,
+    StateClass,
 };
 
+const DOCKER_TIMEOUT: u64 = 120;
+
+/// Container stats streams open at the same time, each one keeps dockerd busy for a
+/// couple of seconds.
+pub const MAX_STATS_STREAMS: usize = 4;
+pub static STATS_STREAMS: Semaphore = Semaphore::const_new(MAX_STATS_STREAMS);
+
+/// Connects to `host` (`unix://`, `tcp://` or `http://`), or the local socket when unset.
+pub fn connect(host: Option<&str>) -> Result<Docker> {
+    let docker = match host {
+        None => Docker::connect_with_socket_defaults()?,
+        Some(host) if host.starts_with("unix://") => {
+            Docker::connect_with_unix(host, DOCKER_TIMEOUT, API_DEFAULT_VERSION)?
+        }
+        Some(host) if host.starts_with("tcp://") || host.starts_with("http://") => {
+            Docker::connect_with_http(host, DOCKER_TIMEOUT, API_DEFAULT_VERSION)?
+        }
+        Some(host) => anyhow::bail!(
+            "Unsupported docker host {}, expected unix://, tcp:// or http://",
+            host
+        ),
+    };
+    Ok(docker)
+}
+
 pub async fn sensor_list(docker: &Docker) -> Vec<Sensor> {
     vec![
         Sensor {
@@ -31,32 +66,89 @@ pub async fn sensor_list(docker: &Docker) -> Vec<Sensor> {
             })),
             ..Default::default()
         },
+        Sensor {
+            id: "docker_containers_stopped".to_string(),
+            name: "Docker Containers Stopped".to_string(),
+            icon: Some("docker".to_string()),
+            reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
+                stat: DockerSensorReporterStat::StoppedCount,
+                docker: Arc::new(docker.clone()),
+            })),
+            ..Default::default()
+        },
+        Sensor {
+            id: "docker_containers_paused".to_string(),
+            name: "Docker Containers Paused".to_string(),
+            icon: Some("docker".to_string()),
+            reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
+                stat: DockerSensorReporterStat::PausedCount,
+                docker: Arc::new(docker.clone()),
+            })),
+            ..Default::default()
+        },
+        Sensor {
+            id: "docker_containers_total".to_string(),
+            name: "Docker Containers Total".to_string(),
+            icon: Some("docker".to_string()),
+            reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
+                stat: DockerSensorReporterStat::TotalCount,
+                docker: Arc::new(docker.clone()),
+            })),
+            ..Default::default()
+        },
         Sensor {
             id: "docker_images_count".to_string(),
             name: "Docker Images".to_string(),
             icon: Some("docker".to_string()),
+            entity_category: Some(EntityCategory::Diagnostic),
             reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
                 stat: DockerSensorReporterStat::ImagesCount,
                 docker: Arc::new(docker.clone()),
             })),
             ..Default::default()
         },
+        Sensor {
+            id: "docker_images_dangling".to_string(),
+            name: "Docker Dangling Images".to_string(),
+            icon: Some("docker".to_string()),
+            entity_category: Some(EntityCategory::Diagnostic),
+            reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
+                stat: DockerSensorReporterStat::DanglingImagesCount,
+                docker: Arc::new(docker.clone()),
+            })),
+            ..Default::default()
+        },
         Sensor {
             id: "docker_images_size".to_string(),
             name: "Docker Images Size".to_string(),
-            icon: Some("data_size".to_string()),
+            icon: Some("harddisk".to_string()),
             device_class: Some(DeviceClass::DataSize),
             unit: Some("B".to_string()),
+            entity_category: Some(EntityCategory::Diagnostic),
             reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
                 stat: DockerSensorReporterStat::ImagesSize,
                 docker: Arc::new(docker.clone()),
             })),
             ..Default::default()
         },
+        Sensor {
+            id: "docker_reclaimable_size".to_string(),
+            name: "Docker Reclaimable Size".to_string(),
+            icon: Some("harddisk".to_string()),
+            device_class: Some(DeviceClass::DataSize),
+            unit: Some("B".to_string()),
+            entity_category: Some(EntityCategory::Diagnostic),
+            reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
+                stat: DockerSensorReporterStat::ReclaimableSize,
+                docker: Arc::new(docker.clone()),
+            })),
+            ..Default::default()
+        },
         Sensor {
             id: "docker_volumes_count".to_string(),
             name: "Docker Volumes".to_string(),
             icon: Some("docker".to_string()),
+            entity_category: Some(EntityCategory::Diagnostic),
             reporter: Some(SensorReporterType::Docker(DockerSensorReporter {
                 stat: DockerSensorReporterStat::VolumesCount,
                 docker: Arc::new(docker.clone()),
@@ -66,13 +158,87 @@ pub async fn sensor_list(docker: &Docker) -> Vec<Sensor> {
     ]
 }
 
-pub async fn container_sensor_list(docker: &Docker, device_name: &str) -> Result<Vec<Sensor>> {
-    Ok(containers(docker)
-        .await?
+/// Bytes `docker system prune` style cleanups can free, following `docker system df`:
+/// unused images (minus layers shared with other images), stopped containers' writable
+/// layers, unreferenced volumes and unused build cache.
+pub fn reclaimable_size(usage: &SystemDataUsageResponse) -> i64 {
+    let images: i64 = usage
+        .images
+        .iter()
+        .flatten()
+        .filter(|image| image.containers == 0)
+        .map(|image| image.size - image.shared_size.max(0))
+        .sum();
+    let containers: i64 = usage
+        .containers
+        .iter()
+        .flatten()
+        .filter(|container| container.state != Some(ContainerSummaryStateEnum::RUNNING))
+        .filter_map(|container| container.size_rw)
+        .sum();
+    let volumes: i64 = usage
+        .volumes
+        .iter()
+        .flatten()
+        .filter_map(|volume| volume.usage_data.as_ref())
+        .filter(|usage_data| usage_data.ref_count == 0)
+        .map(|usage_data| usage_data.size.max(0))
+        .sum();
+    let build_cache: i64 = usage
+        .build_cache
+        .iter()
+        .flatten()
+        .filter(|cache| cache.in_use != Some(true) && cache.shared != Some(true))
+        .filter_map(|cache| cache.size)
+        .sum();
+    images + containers + volumes + build_cache
+}
+
+/// Sensors of every container. Containers whose names end up as the same id, e.g.
+/// `Plex.Server` and `plex_server` or two without a name, get the short container id
+/// added: `dockercontainer_<name>_<short id>_cpu`, so star overrides still match.
+pub fn unique_container_sensors(
+    docker: &Docker,
+    containers: Vec<ContainerSummary>,
+    labels: &[String],
+) -> Vec<Sensor> {
+    let mut sensors: Vec<Sensor> = containers
         .into_iter()
-        .flat_map(|container| container_sensors(docker, device_name, container))
-        .collect::<Vec<Sensor>>())
+        .flat_map(|container| container_sensors(docker, container, labels))
+        .collect();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for sensor in &sensors {
+        *counts.entry(config::sanitize_id(&sensor.id)).or_default() += 1;
+    }
+    for sensor in sensors.iter_mut() {
+        if counts[&config::sanitize_id(&sensor.id)] < 2 {
+            continue;
+        }
+        let Some(SensorReporterType::DockerContainer(reporter)) = &sensor.reporter else {
+            continue;
+        };
+        let short_id = reporter
+            .container
+            .id
+            .as_deref()
+            .unwrap_or_default()
+            .chars()
+            .take(12)
+            .collect::<String>();
+        let Some((prefix, stat)) = sensor.id.rsplit_once('_') else {
+            continue;
+        };
+        let id = format!("{}_{}_{}", prefix, short_id, stat);
+        warn!(
+            "Sensor id {} is used by more than one container, using {}",
+            sensor.id, id
+        );
+        sensor.id = id;
+        sensor.name = format!("{} ({})", sensor.name, short_id);
+    }
+    sensors
 }
+
 pub async fn containers(docker: &Docker) -> Result<Vec<ContainerSummary>> {
     let mut filters = HashMap::new();
     filters.insert("status".into(), vec!["running".into()]);
@@ -86,11 +252,20 @@ pub async fn containers(docker: &Docker) -> Result<Vec<ContainerSummary>> {
     Ok(containers)
 }
 
-fn container_sensors(
+/// The cpu, memory, status and uptime sensors of one container. The container's values of
+/// `labels` are attributes of each of them, when there are any.
+pub fn container_sensors(
     docker: &Docker,
-    device_name: &str,
     container: ContainerSummary,
+    labels: &[String],
 ) -> Vec<Sensor> {
+    let labels = (!labels.is_empty()).then(|| {
+        let container_labels = container.labels.clone().unwrap_or_default();
+        json!(labels
+            .iter()
+            .filter_map(|label| Some((label.clone(), container_labels.get(label)?.clone())))
+            .collect::<BTreeMap<_, _>>())
+    });
     let container = Arc::new(container);
     let container_name = container
         .names
@@ -103,12 +278,14 @@ fn container_sensors(
     vec![
         Sensor {
             id: format!("dockercontainer_{}_cpu", container_name),
-            name: format!("{} Docker {} CPU", device_name, container_name),
-            icon: Some("mdi:cpu-64-bit".to_string()),
+            name: format!("Docker {} CPU", container_name),
+            icon: Some("cpu-64-bit".to_string()),
             unit: Some("%".to_string()),
+            state_class: Some(StateClass::Measurement),
             reporter: Some(SensorReporterType::DockerContainer(
                 DockerContainerSensorReporter {
                     container: container.clone(),
+                    labels: labels.clone(),
                     stats_stash: stats_stash.clone(),
                     stat: DockerContainerSensorReporterStat::CpuUsage,
                     docker: Arc::new(docker.clone()),
@@ -118,13 +295,15 @@ fn container_sensors(
         },
         Sensor {
             id: format!("dockercontainer_{}_memory", container_name),
-            name: format!("{} Docker {} Memory", device_name, container_name),
-            icon: Some("mdi:memory".to_string()),
+            name: format!("Docker {} Memory", container_name),
+            icon: Some("memory".to_string()),
             unit: Some("B".to_string()),
             device_class: Some(DeviceClass::DataSize),
+            state_class: Some(StateClass::Measurement),
             reporter: Some(SensorReporterType::DockerContainer(
                 DockerContainerSensorReporter {
                     container: container.clone(),
+                    labels: labels.clone(),
                     stats_stash: stats_stash.clone(),
                     stat: DockerContainerSensorReporterStat::MemoryUsage,
                     docker: Arc::new(docker.clone()),
@@ -134,11 +313,12 @@ fn container_sensors(
         },
         Sensor {
             id: format!("dockercontainer_{}_uptime", container_name),
-            name: format!("{} Docker {} Uptime", device_name, container_name),
-            icon: Some("mdi:docker".to_string()),
+            name: format!("Docker {} Uptime", container_name),
+            icon: Some("docker".to_string()),
             reporter: Some(SensorReporterType::DockerContainer(
                 DockerContainerSensorReporter {
                     container: container.clone(),
+                    labels: labels.clone(),
                     stats_stash: stats_stash.clone(),
                     stat: DockerContainerSensorReporterStat::Status,
                     docker: Arc::new(docker.clone()),
@@ -0,0 +1,41 @@
+use crate::config::{Sensor, SensorKind, BINARY_SENSOR_ON};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MEASUREMENT: &str = "unraid";
+
+/// Formats the readings as InfluxDB line protocol, one line per sensor with the device
+/// as a tag and the sensor id as the field. Numbers are written as floats, binary
+/// sensors as booleans and everything else as strings.
+pub fn format_lines(device_name: &str, values: &[(Sensor, Option<String>)]) -> Vec<String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    values
+        .iter()
+        .filter_map(|(sensor, value)| {
+            let value = value.as_deref()?;
+            let field = match sensor.kind {
+                SensorKind::BinarySensor => (value == BINARY_SENSOR_ON).to_string(),
+                SensorKind::Sensor => match value.parse::<f64>() {
+                    Ok(number) => number.to_string(),
+                    Err(_) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+                },
+            };
+            Some(format!(
+                "{},device={} {}={} {}",
+                MEASUREMENT,
+                escape_key(device_name),
+                escape_key(&sensor.id),
+                field,
+                timestamp
+            ))
+        })
+        .collect()
+}
+
+fn escape_key(key: &str) -> String {
+    key.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
@@ -1,5 +1,12 @@
 pub mod cli;
+pub mod component_stats;
 pub mod config;
 pub mod docker_stats;
+pub mod influx;
+pub mod log_format;
 pub mod mqtt_config;
+pub mod parsers;
+pub mod prometheus;
+pub mod schema;
+pub mod switch;
 pub mod unraid_stats;
@@ -0,0 +1,82 @@
+//! JSON lines log output. tracing-subscriber's own `json` feature needs tracing-serde, this
+//! writes the few fields we need with serde_json instead, which also does the escaping of
+//! quotes and newlines in values. Unlike the `json` feature only the span names are written,
+//! not the span fields, and non-primitive field values are their `Debug` output as a string.
+use std::fmt;
+
+use serde_json::{json, Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// One JSON object per event with `timestamp`, `level`, `target`, the event `fields` and the
+/// names of the `spans` it happened in.
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut fields = JsonFields(Map::new());
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        let mut line = json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "fields": fields.0,
+        });
+        if let Some(scope) = ctx.event_scope() {
+            line["spans"] = json!(scope
+                .from_root()
+                .map(|span| span.name())
+                .collect::<Vec<_>>());
+        }
+        writeln!(writer, "{}", line)
+    }
+}
+
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+}
@@ -1,56 +1,238 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{bail, Result};
+use clap::{CommandFactory, Parser};
+use rumqttc::QoS;
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::debug;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
 use tracing_subscriber::{fmt, EnvFilter};
 
-mod cli;
-mod config;
-mod docker_stats;
-mod mqtt_config;
-mod unraid_stats;
-use crate::cli::Args;
-use crate::mqtt_config::MqttConfig;
-use crate::unraid_stats::UnraidStats;
+use unraid_mqtt_stats::cli::Args;
+use unraid_mqtt_stats::log_format::{JsonFormat, LogFormat};
+use unraid_mqtt_stats::mqtt_config::{self, Dispatcher, MqttConfig, AVAILABILITY_OFFLINE};
+use unraid_mqtt_stats::unraid_stats::UnraidStats;
+use unraid_mqtt_stats::{influx, prometheus, schema};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+    // before logging is set up, nothing else may end up in the script
+    if let Some(shell) = args.generate_completion {
+        clap_complete::generate(
+            shell,
+            &mut Args::command(),
+            "unraid-mqtt-stats",
+            &mut std::io::stdout(),
+        );
+        return Ok(());
+    }
+
     //LogTracer::init()?;
-    fmt()
+    let subscriber = fmt()
         .with_env_filter(EnvFilter::from_default_env())
-        .with_span_events(fmt::format::FmtSpan::CLOSE)
-        .with_level(true)
-        .with_target(true)
-        .init();
+        .with_ansi(args.log_colors())
+        .with_span_events(fmt::format::FmtSpan::CLOSE);
+    match args.log_format {
+        LogFormat::Text => subscriber.with_level(true).with_target(true).init(),
+        LogFormat::Json => subscriber.event_format(JsonFormat).init(),
+    }
     tracing::trace!("Testing trace output");
     tracing::info!("Testing info output");
 
-    let args = Args::parse();
-    let stats = UnraidStats::new(&args).await?;
-    if let Some(dump_path) = &args.sensor_dump {
+    if args.print_schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema::config_schema())?
+        );
+        return Ok(());
+    }
+
+    let stats = Arc::new(UnraidStats::new(&args).await?);
+    if args.health_check {
+        return health_check(&args, &stats).await;
+    }
+    if let Some(listen) = &args.prometheus_listen {
+        let server = tokio::spawn(prometheus::serve(stats.clone(), listen.clone()));
+        if args.host.is_none() {
+            return server.await?;
+        }
+    }
+
+    if args.list_sensors {
+        stats.print_sensor_list().await?;
+    } else if let Some(dump_path) = &args.sensor_dump {
         debug!("Dumping sensor data to file: {}", dump_path.display());
         stats.dump_sensors_toml(dump_path).await?;
+    } else if args.influx_output {
+        let values = stats.collect_values().await;
+        for line in influx::format_lines(stats.device_name(), &values) {
+            println!("{}", line);
+        }
+        let critical_failed = values
+            .iter()
+            .filter(|(sensor, value)| value.is_none() && sensor.is_critical())
+            .map(|(sensor, _)| sensor.id.clone())
+            .collect::<Vec<_>>();
+        fail_on_critical(&critical_failed)?;
     } else if args.json_output {
-        stats.publish_discovery(None).await?;
-        stats.publish_stats(None).await?;
-    } else {
+        let mut critical_failed = Vec::new();
+        if args.remove_discovery || args.remove_stale_discovery {
+            stats
+                .remove_discovery(None, Vec::new(), args.remove_stale_discovery)
+                .await?;
+        } else {
+            let mut sensors = stats.sensors().await;
+            stats.publish_discovery(None, &sensors).await?;
+            critical_failed = stats.publish_stats(None, &mut sensors).await?;
+        }
+        stats.flush_json_output()?;
+        fail_on_critical(&critical_failed)?;
+    } else if args.remove_discovery || args.remove_stale_discovery {
         let config = MqttConfig::from_args_and_file(&args)?;
         let (client, mut eventloop) = config.create_mqtt_client()?;
 
-        tokio::spawn(async move { while let Ok(_) = eventloop.poll().await {} });
+        let retained = stats
+            .retained_discovery_topics(&client, &mut eventloop)
+            .await?;
+        let eventloop = tokio::spawn(mqtt_config::run_eventloop(
+            eventloop,
+            client.clone(),
+            None,
+            Dispatcher::default(),
+            false,
+        ));
+
+        debug!("Removing Home Assistant discovery messages...");
+        stats
+            .remove_discovery(Some(&client), retained, args.remove_stale_discovery)
+            .await?;
 
-        if !args.skip_discovery {
-            debug!("Publishing Home Assistant discovery messages...");
-            stats.publish_discovery(Some(&client)).await?;
+        mqtt_config::flush(&client, eventloop).await?;
+    } else {
+        let config = MqttConfig::from_args_and_file(&args)?;
+        let (client, mut eventloop) = config.create_mqtt_client()?;
+        if args.dry_run {
+            mqtt_config::wait_for_connection(&mut eventloop).await?;
+            info!("Connected to MQTT broker {}:{}", config.host, config.port);
+        }
+
+        let mut dispatcher = Dispatcher::default();
+        if args.handles_switches() {
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            for topic in stats.switch_command_topics() {
+                dispatcher.route(topic, sender.clone());
+            }
+            let (subscribed_sender, mut subscribed) = mpsc::unbounded_channel();
+            dispatcher.on_subscribed(subscribed_sender);
+            {
+                // after every (re)connect, once Home Assistant can toggle them
+                let stats = stats.clone();
+                let client = client.clone();
+                tokio::spawn(async move {
+                    while subscribed.recv().await.is_some() {
+                        stats.publish_switch_states(Some(&client)).await;
+                    }
+                });
+            }
+            let stats = stats.clone();
+            let client = client.clone();
+            tokio::spawn(async move {
+                while let Some(publish) = receiver.recv().await {
+                    stats.handle_message(&client, publish).await;
+                }
+            });
         }
+        let eventloop = tokio::spawn(mqtt_config::run_eventloop(
+            eventloop,
+            client.clone(),
+            config.availability_topic.clone(),
+            dispatcher,
+            args.interval.is_some(),
+        ));
+
+        let published = async {
+            let mut sensors = stats.sensors().await;
+            if !args.skip_discovery {
+                debug!("Publishing Home Assistant discovery messages...");
+                stats.publish_discovery(Some(&client), &sensors).await?;
+            }
 
-        debug!("Publishing stats...");
-        stats.publish_stats(Some(&client)).await?;
+            let mut hangup = signal(SignalKind::hangup())?;
+            let mut critical_failed;
+            loop {
+                debug!("Publishing stats...");
+                critical_failed = stats.publish_stats(Some(&client), &mut sensors).await?;
 
-        tokio::time::sleep(Duration::from_secs(2)).await;
+                let Some(interval) = args.interval else {
+                    break;
+                };
+                if !critical_failed.is_empty() {
+                    error!("Critical sensors failed: {}", critical_failed.join(", "));
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(interval.into())) => {}
+                    _ = tokio::signal::ctrl_c() => {
+                        debug!("Shutting down...");
+                        if let Some(topic) = &config.availability_topic {
+                            client
+                                .publish(topic, QoS::AtLeastOnce, true, AVAILABILITY_OFFLINE)
+                                .await?;
+                        }
+                        break;
+                    }
+                    _ = hangup.recv() => {
+                        info!("Reloading config...");
+                        if let Err(e) = stats.reload_config() {
+                            error!("Failed to reload config, keeping the current one: {:#}", e);
+                        }
+                    }
+                }
+
+                // rebuild so container stats are fresh, new containers get discovered and
+                // reloaded overrides apply. Only changed discovery configs are republished.
+                stats.clear_cache();
+                sensors = stats.sensors().await;
+                if !args.skip_discovery {
+                    stats.publish_discovery(Some(&client), &sensors).await?;
+                }
+            }
+            anyhow::Ok(critical_failed)
+        }
+        .await;
+        let critical_failed = match published {
+            Ok(critical_failed) => critical_failed,
+            // publishing fails once the event loop is gone, its error says why
+            Err(e) if eventloop.is_finished() => return Err(eventloop.await?.err().unwrap_or(e)),
+            Err(e) => return Err(e),
+        };
+
+        mqtt_config::flush(&client, eventloop).await?;
+        fail_on_critical(&critical_failed)?;
 
         debug!("Stats published successfully!");
     }
 
     Ok(())
 }
+
+/// Connects to the broker without publishing anything, then asks Docker for a ping.
+async fn health_check(args: &Args, stats: &UnraidStats) -> Result<()> {
+    if args.host.is_some() {
+        let config = MqttConfig::from_args_and_file(args)?;
+        let (client, mut eventloop) = config.create_mqtt_client()?;
+        mqtt_config::wait_for_connection(&mut eventloop).await?;
+        client.disconnect().await?;
+        info!("MQTT broker {}:{} is reachable", config.host, config.port);
+    }
+    stats.check_docker().await?;
+    Ok(())
+}
+
+/// Everything else has been published by now, the non-zero exit is for cron/systemd.
+fn fail_on_critical(critical_failed: &[String]) -> Result<()> {
+    if !critical_failed.is_empty() {
+        bail!("Critical sensors failed: {}", critical_failed.join(", "));
+    }
+    Ok(())
+}
@@ -1,7 +1,24 @@
 use crate::cli::Args;
-use anyhow::Result;
-use rumqttc::{AsyncClient, EventLoop, MqttOptions};
+use anyhow::{Context, Result};
+use rumqttc::{
+    AsyncClient, ConnectionError, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet,
+    Publish, QoS, SubscribeFilter,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, trace, warn};
+
+pub const AVAILABILITY_ONLINE: &str = "online";
+pub const AVAILABILITY_OFFLINE: &str = "offline";
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub struct MqttConfig {
@@ -10,6 +27,8 @@ pub struct MqttConfig {
     pub client_id: String,
     pub username: String,
     pub password: String,
+    pub availability_topic: Option<String>,
+    pub max_packet_size: usize,
 }
 
 impl MqttConfig {
@@ -20,6 +39,8 @@ impl MqttConfig {
             client_id: String::new(),
             username: String::new(),
             password: String::new(),
+            availability_topic: args.availability_topic(),
+            max_packet_size: args.max_packet_size,
         };
 
         if let Some(host) = &args.host {
@@ -34,6 +55,12 @@ impl MqttConfig {
         if let Some(password) = &args.password {
             config.password = password.clone();
         }
+        if let Some(path) = &args.username_file {
+            config.username = read_secret_file(path)?;
+        }
+        if let Some(path) = &args.password_file {
+            config.password = read_secret_file(path)?;
+        }
 
         if config.host.is_empty() {
             anyhow::bail!(
@@ -55,8 +82,200 @@ impl MqttConfig {
         }
 
         mqtt_options.set_keep_alive(Duration::from_secs(5));
+        mqtt_options.set_max_packet_size(self.max_packet_size, self.max_packet_size);
+        if let Some(topic) = &self.availability_topic {
+            mqtt_options.set_last_will(LastWill::new(
+                topic,
+                AVAILABILITY_OFFLINE,
+                QoS::AtLeastOnce,
+                true,
+            ));
+        }
 
         let (client, eventloop) = AsyncClient::new(mqtt_options, 10);
         Ok((client, eventloop))
     }
 }
+
+/// QoS for a level from the command line, which only accepts 0 to 2.
+pub fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// Reads a credential from a file, without the trailing newline most editors and
+/// `echo` add.
+pub fn read_secret_file(path: &Path) -> Result<String> {
+    let secret = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read secret file {}", path.display()))?;
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Size of a QoS 1 publish packet as rumqttc checks it against the max packet size: fixed
+/// header, topic, packet id and payload.
+pub fn publish_packet_size(topic: &str, payload_len: usize) -> usize {
+    let len = 2 + topic.len() + 2 + payload_len;
+    let remaining_len_size = match len {
+        0..=127 => 1,
+        128..=16_383 => 2,
+        16_384..=2_097_151 => 3,
+        _ => 4,
+    };
+    1 + remaining_len_size + len
+}
+
+/// Polls the event loop until the broker accepts the connection, failing on the first
+/// connection error instead of retrying.
+pub async fn wait_for_connection(eventloop: &mut EventLoop) -> Result<()> {
+    let connected = async {
+        loop {
+            if let Event::Incoming(Packet::ConnAck(_)) = eventloop.poll().await? {
+                return anyhow::Ok(());
+            }
+        }
+    };
+    match tokio::time::timeout(CONNECT_TIMEOUT, connected).await {
+        Ok(result) => result,
+        Err(_) => anyhow::bail!(
+            "Timed out after {:?} connecting to the MQTT broker",
+            CONNECT_TIMEOUT
+        ),
+    }
+}
+
+/// Drives the MQTT event loop until a disconnect has been sent. With `reconnect` (the
+/// `--interval` daemon) connection errors are logged and retried with a capped exponential
+/// backoff, otherwise the first one ends the loop so pending publishes fail instead of
+/// waiting for a broker that may never come back. After every (re)connect the availability
+/// topic is set back to online and the routed topics are subscribed to again.
+pub async fn run_eventloop(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    availability_topic: Option<String>,
+    dispatcher: Dispatcher,
+    reconnect: bool,
+) -> Result<()> {
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                debug!("Connected to MQTT broker");
+                delay = RECONNECT_DELAY;
+                // try_*, awaiting here would block the loop that drains the queue
+                if let Some(topic) = &availability_topic {
+                    if let Err(e) =
+                        client.try_publish(topic, QoS::AtLeastOnce, true, AVAILABILITY_ONLINE)
+                    {
+                        warn!("Failed to publish availability: {}", e);
+                    }
+                }
+                // the session is clean, subscriptions are gone after a reconnect. One packet,
+                // so there is a single SubAck once all of them are live
+                let filters = dispatcher
+                    .topics()
+                    .map(|topic| SubscribeFilter::new(topic.clone(), QoS::AtLeastOnce))
+                    .collect::<Vec<_>>();
+                if !filters.is_empty() {
+                    if let Err(e) = client.try_subscribe_many(filters) {
+                        warn!("Failed to subscribe to command topics: {}", e);
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::SubAck(_))) => {
+                dispatcher.subscribed();
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                dispatcher.dispatch(publish);
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                debug!("Disconnected from MQTT broker");
+                return Ok(());
+            }
+            Ok(event) => trace!("MQTT event: {:?}", event),
+            Err(e) => {
+                if let ConnectionError::ConnectionRefused(code) = e {
+                    error!("MQTT broker refused the connection: {:?}", code);
+                } else {
+                    warn!("MQTT connection error: {}", e);
+                }
+                if !reconnect {
+                    return Err(e).context("MQTT connection failed");
+                }
+                debug!("Reconnecting to MQTT broker in {:?}", delay);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Routes incoming publishes by their exact topic, every routed topic is subscribed to
+/// when connected. Messages on other topics are only logged.
+#[derive(Default)]
+pub struct Dispatcher {
+    routes: HashMap<String, mpsc::UnboundedSender<Publish>>,
+    subscribed: Option<mpsc::UnboundedSender<()>>,
+}
+
+impl Dispatcher {
+    pub fn route(&mut self, topic: String, sender: mpsc::UnboundedSender<Publish>) {
+        self.routes.insert(topic, sender);
+    }
+
+    /// Gets a message every time the broker has confirmed the subscriptions, after each
+    /// (re)connect.
+    pub fn on_subscribed(&mut self, sender: mpsc::UnboundedSender<()>) {
+        self.subscribed = Some(sender);
+    }
+
+    pub fn subscribed(&self) {
+        debug!("Subscribed to {} topics", self.routes.len());
+        if let Some(sender) = &self.subscribed {
+            let _ = sender.send(());
+        }
+    }
+
+    pub fn topics(&self) -> impl Iterator<Item = &String> {
+        self.routes.keys()
+    }
+
+    /// Hands the message to the route for its topic, false when there is none or its
+    /// receiver is gone.
+    pub fn dispatch(&self, publish: Publish) -> bool {
+        debug!(
+            "Received {} bytes on {}",
+            publish.payload.len(),
+            publish.topic
+        );
+        match self.routes.get(publish.topic.as_str()) {
+            Some(sender) => sender.send(publish).is_ok(),
+            None => {
+                debug!("No route for messages on {}", publish.topic);
+                false
+            }
+        }
+    }
+}
+
+/// Queues a disconnect behind everything already published and waits for the event loop
+/// to write it out, so the broker has received every earlier publish. Fails with the error
+/// that ended the event loop when it stopped early.
+pub async fn flush(client: &AsyncClient, eventloop: JoinHandle<Result<()>>) -> Result<()> {
+    let flushed = async {
+        let disconnected = client.disconnect().await;
+        // a stopped event loop knows why the disconnect couldn't be queued
+        eventloop.await??;
+        disconnected?;
+        anyhow::Ok(())
+    };
+    match tokio::time::timeout(FLUSH_TIMEOUT, flushed).await {
+        Ok(result) => result,
+        Err(_) => anyhow::bail!(
+            "Timed out after {:?} waiting for MQTT messages to be sent",
+            FLUSH_TIMEOUT
+        ),
+    }
+}
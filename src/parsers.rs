@@ -0,0 +1,302 @@
+//! Parsers for the output of the commands and files the built-in sensors read. They only
+//! take text, `tests/parsers.rs` runs them on output captured from real servers.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bollard::secret::ContainerStatsResponse;
+
+/// Sizes are in bytes, `df` has to be run with `-B1`.
+#[derive(Debug)]
+pub struct DiskInfo {
+    pub filesystem: String,
+    pub total: u64,
+    pub used: u64,
+    pub available: u64,
+    pub usage_percent: f64,
+    pub mountpoint: String,
+}
+
+/// Parses the first filesystem from `df` output, see [`parse_disk_usage_all`].
+pub fn parse_disk_usage(df_output: &str) -> Option<DiskInfo> {
+    parse_disk_usage_all(df_output).into_iter().next()
+}
+
+/// Parses every filesystem from `df` output, picking columns by their header so both
+/// POSIX (`-P`) and GNU layouts work. A long filesystem name that `df` wrapped onto its own
+/// line is joined with the line after it. Lines that don't parse are skipped.
+pub fn parse_disk_usage_all(df_output: &str) -> Vec<DiskInfo> {
+    let mut lines = df_output.lines();
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let header: Vec<&str> = header.split_whitespace().collect();
+    let column = |matches: &dyn Fn(&str) -> bool| header.iter().position(|name| matches(name));
+    let (Some(total), Some(used), Some(available), Some(usage), Some(mountpoint)) = (
+        column(&|name| name.ends_with("blocks") || name == "Size"),
+        column(&|name| name == "Used"),
+        column(&|name| name == "Available" || name == "Avail"),
+        column(&|name| name == "Use%" || name == "Capacity"),
+        column(&|name| name == "Mounted"),
+    ) else {
+        return Vec::new();
+    };
+
+    let mut disks = Vec::new();
+    while let Some(line) = lines.next() {
+        let mut parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() == 1 {
+            parts.extend(lines.next().unwrap_or_default().split_whitespace());
+        }
+        if parts.len() <= mountpoint {
+            continue;
+        }
+        let disk = (|| {
+            Some(DiskInfo {
+                filesystem: parts[0].to_string(),
+                total: parts[total].parse().ok()?,
+                used: parts[used].parse().ok()?,
+                available: parts[available].parse().ok()?,
+                usage_percent: parts[usage].trim_end_matches('%').parse().ok()?,
+                mountpoint: parts[mountpoint..].join(" "),
+            })
+        })();
+        disks.extend(disk);
+    }
+    disks
+}
+
+/// Array data disks are mounted at `/mnt/diskN`, pools and the user share are not counted.
+pub fn is_array_disk(mountpoint: &str) -> bool {
+    mountpoint
+        .strip_prefix("/mnt/disk")
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Sums `total` and `available` over the array disks in `df` output, `None` when there are none.
+pub fn array_disk_totals(df_output: &str) -> Option<(u64, u64)> {
+    let disks: Vec<DiskInfo> = parse_disk_usage_all(df_output)
+        .into_iter()
+        .filter(|disk| is_array_disk(&disk.mountpoint))
+        .collect();
+    if disks.is_empty() {
+        return None;
+    }
+    Some(disks.iter().fold((0, 0), |(total, available), disk| {
+        (total + disk.total, available + disk.available)
+    }))
+}
+
+#[derive(Debug)]
+pub struct InodeInfo {
+    pub filesystem: String,
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+    pub mountpoint: String,
+}
+
+/// Parses the first filesystem from `df -i` output, columns are picked by their header
+/// like [`parse_disk_usage`].
+pub fn parse_inode_usage(df_output: &str) -> Option<InodeInfo> {
+    let mut lines = df_output.lines();
+    let header: Vec<&str> = lines.next()?.split_whitespace().collect();
+    let column = |name: &str| header.iter().position(|header_name| *header_name == name);
+    let total = column("Inodes")?;
+    let used = column("IUsed")?;
+    let free = column("IFree")?;
+    let mountpoint = column("Mounted")?;
+
+    let mut parts: Vec<&str> = lines.next()?.split_whitespace().collect();
+    if parts.len() == 1 {
+        parts.extend(lines.next()?.split_whitespace());
+    }
+    if parts.len() <= mountpoint {
+        return None;
+    }
+
+    Some(InodeInfo {
+        filesystem: parts[0].to_string(),
+        total: parts[total].parse().ok()?,
+        used: parts[used].parse().ok()?,
+        free: parts[free].parse().ok()?,
+        mountpoint: parts[mountpoint..].join(" "),
+    })
+}
+
+/// `drive state is:  active/idle` from `hdparm -C`, true unless the drive is in standby
+/// or sleeping. `None` for an unknown state.
+pub fn parse_spin_state(hdparm_output: &str) -> Option<bool> {
+    let state = hdparm_output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("drive state is:"))?
+        .trim();
+    match state {
+        "active/idle" | "active" | "idle" => Some(true),
+        "standby" | "sleeping" => Some(false),
+        _ => None,
+    }
+}
+
+/// `fanN: 1200 RPM` lines from lm-sensors output as `(N, rpm)`. When several chips have
+/// the same fan number only the first one is kept.
+pub fn parse_fan_speeds(sensors_output: &str) -> Vec<(u32, u32)> {
+    let mut fans: Vec<(u32, u32)> = Vec::new();
+    for line in sensors_output.lines() {
+        let Some((label, reading)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let Some(Ok(number)) = label.strip_prefix("fan").map(str::parse::<u32>) else {
+            continue;
+        };
+        let mut words = reading.split_whitespace();
+        let (Some(Ok(rpm)), Some("RPM")) = (words.next().map(str::parse::<u32>), words.next())
+        else {
+            continue;
+        };
+        if fans.iter().all(|(fan, _)| *fan != number) {
+            fans.push((number, rpm));
+        }
+    }
+    fans
+}
+
+/// Labels tried for `cpu_temp` when the config has no `cpu_temp_labels`: Intel, then AMD.
+pub const DEFAULT_CPU_TEMP_LABELS: &[&str] = &["Package id 0", "Tctl", "Tdie", "Tccd1", "CPU Temp"];
+
+/// First label from `labels` that has a reading in lm-sensors output, as `(label, °C)`.
+/// Labels are tried in order, not in the order they appear in the output.
+pub fn parse_cpu_temp(sensors_output: &str, labels: &[String]) -> Option<(String, f64)> {
+    labels.iter().find_map(|label| {
+        let prefix = format!("{}:", label);
+        sensors_output
+            .lines()
+            .find(|line| line.trim_start().starts_with(&prefix))
+            .and_then(|line| {
+                line.split_whitespace()
+                    .find(|word| word.contains("°C"))
+                    .and_then(|temp| {
+                        temp.trim_start_matches('+')
+                            .trim_end_matches("°C")
+                            .parse::<f64>()
+                            .ok()
+                    })
+            })
+            .map(|temp| (label.clone(), temp))
+    })
+}
+
+/// Counters of `/proc/vmstat`, `name value` per line.
+pub fn parse_vmstat(content: &str) -> HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(' ')?;
+            Some((name.to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// `some avg10` of `/proc/pressure/memory`, the percent of the last 10 seconds at least one
+/// task was stalled waiting for memory.
+pub fn parse_memory_pressure(content: &str) -> Option<f64> {
+    content
+        .lines()
+        .find(|line| line.starts_with("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+/// The `key="value"` lines of `/etc/unraid-version`.
+#[derive(Debug, Default, PartialEq)]
+pub struct UnraidVersion {
+    pub version: Option<String>,
+    /// Every other key, e.g. a build date.
+    pub extra: BTreeMap<String, String>,
+}
+
+/// Missing keys stay unset, lines without a `=` are skipped.
+pub fn parse_unraid_version(content: &str) -> UnraidVersion {
+    let mut unraid_version = UnraidVersion::default();
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_string();
+        match key.trim() {
+            "version" => unraid_version.version = Some(value),
+            key => {
+                unraid_version.extra.insert(key.to_string(), value);
+            }
+        }
+    }
+    unraid_version
+}
+
+/// Labels for `mdState` values, matched on the part before any `:` detail
+/// (e.g. `ERROR:TOO_MANY_MISSING_DISKS`).
+const ARRAY_STATUS_LABELS: &[(&str, &str)] = &[
+    ("STARTED", "Started"),
+    ("STOPPED", "Stopped"),
+    ("NEW_ARRAY", "New Array"),
+    ("ERROR", "Error"),
+];
+
+/// Maps a raw `mdState` to a readable label, unknown states are returned unchanged.
+pub fn array_status_label(status: &str) -> &str {
+    let state = status.split(':').next().unwrap_or(status);
+    ARRAY_STATUS_LABELS
+        .iter()
+        .find(|(raw, _)| *raw == state)
+        .map_or(status, |(_, label)| label)
+}
+
+/// The `mdState` of `mdcmd status` output.
+pub fn parse_array_status(status_output: &str) -> Option<String> {
+    status_output
+        .lines()
+        .find(|line| line.starts_with("mdState="))
+        .map(|line| line.trim_start_matches("mdState=").to_string())
+}
+
+// https://github.com/home-assistant/core/blob/dev/homeassistant/const.py#L619
+// mimic https://github.com/docker/cli/blob/4debf411d1e6efbd9ce65e4250718e9c529a6525/cli/command/container/stats_helpers.go#L166
+/// CPU percent of a container from a stats response, 100 per fully used core like `docker
+/// stats`. Without `precpu_stats` (the first sample of a stream) it is the usage since the
+/// container started, 0 when a counter went backwards, e.g. after a restart.
+pub fn calculate_cpu_percent(stats: &ContainerStatsResponse) -> f64 {
+    let cpu_stats = &stats.cpu_stats;
+    let precpu_stats = &stats.precpu_stats;
+
+    let cpu_delta = cpu_stats
+        .as_ref()
+        .and_then(|c| c.cpu_usage.as_ref().and_then(|c| c.total_usage))
+        .unwrap_or_default()
+        .saturating_sub(
+            precpu_stats
+                .as_ref()
+                .and_then(|c| c.cpu_usage.as_ref().and_then(|c| c.total_usage))
+                .unwrap_or_default(),
+        );
+    let system_delta = cpu_stats
+        .as_ref()
+        .and_then(|c| c.system_cpu_usage)
+        .unwrap_or_default()
+        .saturating_sub(
+            precpu_stats
+                .as_ref()
+                .and_then(|c| c.system_cpu_usage)
+                .unwrap_or_default(),
+        );
+
+    if system_delta > 0 && cpu_delta > 0 {
+        let cpu_count = cpu_stats
+            .as_ref()
+            .and_then(|c| c.online_cpus)
+            .unwrap_or_default() as f64;
+        (cpu_delta as f64 / system_delta as f64) * cpu_count * 100.0
+    } else {
+        0.0
+    }
+}
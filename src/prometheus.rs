@@ -0,0 +1,106 @@
+use crate::config::{Sensor, SensorKind, BINARY_SENSOR_ON};
+use crate::unraid_stats::{SensorCache, UnraidStats};
+use anyhow::Result;
+use std::fmt::Write;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Serves `/metrics` in the Prometheus text exposition format until the process exits.
+pub async fn serve(stats: Arc<UnraidStats>, listen: String) -> Result<()> {
+    let listener = TcpListener::bind(&listen).await?;
+    info!("Serving Prometheus metrics on http://{}/metrics", listen);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(&stats, stream).await {
+                warn!("Prometheus request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(stats: &UnraidStats, mut stream: TcpStream) -> Result<()> {
+    let mut buffer = vec![0; 4096];
+    let read = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let path = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("");
+    debug!("Prometheus request for {}", path);
+
+    let (status, body) = if path == "/metrics" {
+        // every scrape is its own run with its own cache, new containers show up on the
+        // next one and the MQTT loop keeps its sensors
+        let values = stats.collect_values_with(&SensorCache::default()).await;
+        ("200 OK", format_metrics(stats.device_name(), &values))
+    } else {
+        ("404 Not Found", "Not Found\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Formats every numeric reading as a gauge named after the sensor id. Binary sensors
+/// report 1/0, values that are not numbers (like array status) are skipped.
+pub fn format_metrics(device_name: &str, values: &[(Sensor, Option<String>)]) -> String {
+    let mut output = String::new();
+    for (sensor, value) in values {
+        let Some(value) = value.as_deref() else {
+            continue;
+        };
+        let value = match sensor.kind {
+            SensorKind::BinarySensor => {
+                if value == BINARY_SENSOR_ON {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            SensorKind::Sensor => match value.parse::<f64>() {
+                Ok(value) => value,
+                Err(_) => continue,
+            },
+        };
+        let name = metric_name(&sensor.id);
+        let _ = writeln!(output, "# HELP {} {}", name, escape_help(&sensor.name));
+        let _ = writeln!(output, "# TYPE {} gauge", name);
+        let _ = writeln!(
+            output,
+            "{}{{device=\"{}\"}} {}",
+            name,
+            escape_label_value(device_name),
+            value
+        );
+    }
+    output
+}
+
+/// HELP text may contain anything but a raw newline, `\` and newlines are escaped.
+fn escape_help(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Label values additionally escape `"`, which ends the value.
+fn escape_label_value(value: &str) -> String {
+    escape_help(value).replace('"', "\\\"")
+}
+
+fn metric_name(id: &str) -> String {
+    let id = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    format!("unraid_{}", id)
+}
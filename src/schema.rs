@@ -0,0 +1,12 @@
+//! JSON Schema of the config file, printed by `--print-schema` for editor validation and
+//! completion. Derived from the structs in [`crate::config`] and their serde attributes.
+
+use crate::config::Config;
+use serde_json::Value;
+
+/// Schema for a whole config file, toml, json or yaml.
+pub fn config_schema() -> Value {
+    let mut schema = schemars::schema_for!(Config);
+    schema.insert("title".to_string(), "unraid-mqtt-stats config".into());
+    schema.to_value()
+}
@@ -0,0 +1,78 @@
+use crate::config::{self, SwitchConfig, BINARY_SENSOR_OFF, BINARY_SENSOR_ON};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use tokio::process::Command;
+use tracing::{debug, instrument};
+
+const COMPONENT: &str = "switch";
+
+impl SwitchConfig {
+    pub fn object_id(&self) -> String {
+        config::sanitize_id(&self.id)
+    }
+
+    pub fn discovery_topic(&self, discovery_prefix: &str, node_id: &str) -> String {
+        format!(
+            "{}/{}/{}/{}/config",
+            discovery_prefix,
+            COMPONENT,
+            node_id,
+            self.object_id()
+        )
+    }
+
+    /// Home Assistant publishes `ON`/`OFF` here when the switch is toggled.
+    pub fn command_topic(&self, node_id: &str) -> String {
+        format!("{}/{}/{}/set", node_id, COMPONENT, self.object_id())
+    }
+
+    pub fn state_topic(&self, node_id: &str) -> String {
+        format!("{}/{}/{}/state", node_id, COMPONENT, self.object_id())
+    }
+
+    pub fn discovery_config(&self, node_id: &str, device_info: &Value) -> Value {
+        let mut config = json!({
+            "name": self.name,
+            "has_entity_name": true,
+            "command_topic": self.command_topic(node_id),
+            "state_topic": self.state_topic(node_id),
+            "payload_on": BINARY_SENSOR_ON,
+            "payload_off": BINARY_SENSOR_OFF,
+            "unique_id": format!("{}_{}", node_id, self.object_id()),
+            "object_id": format!("{}_{}", config::entity_id_prefix(node_id), self.object_id()),
+            "device": device_info,
+            "origin": {
+                "name": env!("CARGO_PKG_NAME"),
+                "sw_version": env!("CARGO_PKG_VERSION"),
+            },
+        });
+        if let Some(icon) = &self.icon {
+            config["icon"] = json!(config::icon_name(icon));
+        }
+        config
+    }
+
+    /// Runs the command for an `ON`/`OFF` payload and returns the state to publish back.
+    /// A command that fails leaves the state alone.
+    #[instrument(level = "debug", skip(self), fields(switch = %self.id))]
+    pub async fn run(&self, payload: &str) -> Result<&'static str> {
+        let (command, args, state) = match payload {
+            BINARY_SENSOR_ON => (&self.command_on, &self.args_on, BINARY_SENSOR_ON),
+            BINARY_SENSOR_OFF => (&self.command_off, &self.args_off, BINARY_SENSOR_OFF),
+            _ => bail!("Unknown payload {:?} for switch {}", payload, self.id),
+        };
+        let mut command = Command::new(command);
+        if let Some(args) = args {
+            command.args(args);
+        }
+        let status = command
+            .status()
+            .await
+            .with_context(|| format!("Failed to run the command of switch {}", self.id))?;
+        if !status.success() {
+            bail!("Command of switch {} exited with {}", self.id, status);
+        }
+        debug!("Switch {} turned {}", self.id, state);
+        Ok(state)
+    }
+}
@@ -1,54 +1,475 @@
 use crate::cli::Args;
+use crate::component_stats::{self, ComponentFilter};
 use crate::config::{
-    self, CommandSensorReporter, Config, DeviceClass, Sensor, SensorReporterType, Sensors,
-    SensorsDump, SystemSensorReporter, SystemSensorReporterStat,
+    self, CollectionStat, CommandSensorReporter, Config, DeviceClass, EntityCategory,
+    FileRateReporter, FileSensorReporter, MemoryUnit, Sensor, SensorKind, SensorReporterType,
+    Sensors, SensorsDump, StateClass, StaticSensorReporter, SwitchConfig, SystemSensorReporter,
+    SystemSensorReporterStat, TempUnit, BINARY_SENSOR_OFF, BINARY_SENSOR_ON,
+};
+use crate::docker_stats::{self, unique_container_sensors};
+use crate::mqtt_config::{self, AVAILABILITY_OFFLINE, AVAILABILITY_ONLINE};
+use crate::parsers::{
+    array_disk_totals, array_status_label, parse_array_status, parse_cpu_temp, parse_disk_usage,
+    parse_fan_speeds, parse_inode_usage, parse_memory_pressure, parse_spin_state,
+    parse_unraid_version, parse_vmstat, DEFAULT_CPU_TEMP_LABELS,
 };
-use crate::docker_stats::{self, container_sensor_list};
 use anyhow::Result;
+use bollard::secret::ContainerSummary;
 use bollard::Docker;
-use rumqttc::{AsyncClient, QoS};
+use futures_util::future::join_all;
+use futures_util::FutureExt;
+use rumqttc::{AsyncClient, Event, EventLoop, Packet, Publish, QoS};
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
-use std::sync::Arc;
-use sysinfo::System;
-use tracing::{debug, instrument};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, instrument, warn, Instrument};
+
+const PUBLISH_ATTEMPTS: u32 = 3;
+const PUBLISH_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+struct SensorReading<'a> {
+    sensor: &'a Sensor,
+    value: Option<String>,
+    attributes: Option<serde_json::Value>,
+}
 
 #[derive(Debug)]
 pub struct UnraidStats {
-    sensor_config: Option<Config>,
-    docker: Docker,
+    config_files: Vec<PathBuf>,
+    sensor_config: RwLock<Option<Config>>,
+    docker: Option<Docker>,
     json_output: bool,
     discovery_prefix: String,
     device_name: String,
+    node_id: String,
+    device_model: String,
+    device_manufacturer: String,
+    device_sw_version: Option<String>,
+    device_identifier: String,
     skip_discovery: bool,
+    expire_after: Option<u32>,
+    collect_concurrency: usize,
+    component_filter: ComponentFilter,
+    disabled_prefixes: Vec<String>,
+    availability_topic: Option<String>,
+    json_array: Option<Mutex<Vec<serde_json::Value>>>,
+    dry_run: bool,
+    retain_state: bool,
+    max_packet_size: usize,
+    discovery_qos: QoS,
+    discovery_retain: bool,
+    precision: u8,
+    temp_unit: TempUnit,
+    json_state_topic: Option<String>,
+    /// Last value of every sensor, published as one object with `json_state_topic`.
+    json_state: Mutex<serde_json::Map<String, serde_json::Value>>,
+    published_discovery: Mutex<HashMap<String, String>>,
+    /// Unit mismatches already logged, sensors are rebuilt every daemon cycle.
+    unit_warnings: Mutex<HashSet<String>>,
+    interval: Option<u32>,
+    handle_switches: bool,
+    /// Last state each switch was turned to, `OFF` until Home Assistant toggles it.
+    switch_states: Mutex<HashMap<String, &'static str>>,
+    last_collected: Mutex<HashMap<String, Instant>>,
+    /// Shared by the MQTT and json runs, cleared between daemon cycles.
+    cache: SensorCache,
 }
 
-impl UnraidStats {
-    pub async fn new(args: &Args) -> Result<Self> {
-        let docker = Docker::connect_with_socket_defaults()?;
-        let sensor_config = args
-            .config_file
-            .as_ref()
-            .map(|file| config::load_config(file));
+/// The container list and the sensors built from it, kept for one run so every caller of
+/// [`UnraidStats::sensors_with`] gets the same set and container stats are fetched once.
+#[derive(Default)]
+pub struct SensorCache {
+    containers: Mutex<Option<Vec<ContainerSummary>>>,
+    sensors: Mutex<Option<Vec<Sensor>>>,
+}
+
+impl SensorCache {
+    pub fn clear(&self) {
+        self.containers.lock().unwrap().take();
+        self.sensors.lock().unwrap().take();
+    }
+}
+
+impl fmt::Debug for SensorCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SensorCache")
+            .field(
+                "containers",
+                &self.containers.lock().unwrap().as_ref().map(Vec::len),
+            )
+            .field(
+                "sensors",
+                &self.sensors.lock().unwrap().as_ref().map(Vec::len),
+            )
+            .finish()
+    }
+}
+
+/// Builds an [`UnraidStats`] without going through the command line [`Args`]. The defaults
+/// match the command line defaults, docker sensors are only added when a handle is given.
+pub struct UnraidStatsBuilder {
+    config: Option<Config>,
+    config_files: Vec<PathBuf>,
+    docker: Option<Docker>,
+    json_output: bool,
+    json_array: bool,
+    discovery_prefix: String,
+    device_name: String,
+    node_id: Option<String>,
+    device_model: String,
+    device_manufacturer: String,
+    device_sw_version: Option<String>,
+    device_id_suffix: Option<String>,
+    skip_discovery: bool,
+    expire_after: Option<u32>,
+    interval: Option<u32>,
+    handle_switches: bool,
+    collect_concurrency: usize,
+    component_filter: ComponentFilter,
+    disabled_prefixes: Vec<String>,
+    availability_topic: Option<String>,
+    dry_run: bool,
+    retain_state: bool,
+    max_packet_size: usize,
+    discovery_qos: QoS,
+    discovery_retain: bool,
+    precision: u8,
+    temp_unit: TempUnit,
+    json_state_topic: Option<String>,
+}
+
+impl Default for UnraidStatsBuilder {
+    fn default() -> Self {
+        UnraidStatsBuilder {
+            config: None,
+            config_files: Vec::new(),
+            docker: None,
+            json_output: false,
+            json_array: false,
+            discovery_prefix: "homeassistant".to_string(),
+            device_name: "unraid".to_string(),
+            node_id: None,
+            device_model: "Unraid Server".to_string(),
+            device_manufacturer: "Lime Technology".to_string(),
+            device_sw_version: None,
+            device_id_suffix: None,
+            skip_discovery: false,
+            expire_after: None,
+            interval: None,
+            handle_switches: false,
+            collect_concurrency: 8,
+            component_filter: ComponentFilter::default(),
+            disabled_prefixes: Vec::new(),
+            availability_topic: None,
+            dry_run: false,
+            retain_state: false,
+            max_packet_size: 10 * 1024,
+            discovery_qos: QoS::AtLeastOnce,
+            discovery_retain: true,
+            precision: 1,
+            temp_unit: TempUnit::C,
+            json_state_topic: None,
+        }
+    }
+}
+
+impl UnraidStatsBuilder {
+    /// Sensor overrides and custom sensors, merged over the ones from `config_files`.
+    /// [`UnraidStats::reload_config`] only re-reads the files.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Config files loaded on `build` and by [`UnraidStats::reload_config`].
+    pub fn config_files(mut self, config_files: Vec<PathBuf>) -> Self {
+        self.config_files = config_files;
+        self
+    }
+
+    pub fn docker(mut self, docker: Option<Docker>) -> Self {
+        self.docker = docker;
+        self
+    }
+
+    /// Print what would be published as JSON on stdout instead of publishing it.
+    pub fn json_output(mut self, json_output: bool) -> Self {
+        self.json_output = json_output;
+        self
+    }
+
+    /// With `json_output`, buffer the records for [`UnraidStats::flush_json_output`].
+    pub fn json_array(mut self, json_array: bool) -> Self {
+        self.json_array = json_array;
+        self
+    }
+
+    pub fn discovery_prefix(mut self, discovery_prefix: &str) -> Self {
+        self.discovery_prefix = discovery_prefix.to_string();
+        self
+    }
+
+    pub fn device_name(mut self, device_name: &str) -> Self {
+        self.device_name = device_name.to_string();
+        self
+    }
+
+    /// Defaults to `unraid_<device_name>`.
+    pub fn node_id(mut self, node_id: String) -> Self {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    pub fn device_model(mut self, device_model: &str) -> Self {
+        self.device_model = device_model.to_string();
+        self
+    }
+
+    pub fn device_manufacturer(mut self, device_manufacturer: &str) -> Self {
+        self.device_manufacturer = device_manufacturer.to_string();
+        self
+    }
+
+    pub fn device_sw_version(mut self, device_sw_version: Option<String>) -> Self {
+        self.device_sw_version = device_sw_version;
+        self
+    }
+
+    /// Appended to the node id in the device identifiers. Unset or [`MACHINE_ID_SUFFIX`]
+    /// appends [`machine_id_suffix`], [`NO_DEVICE_ID_SUFFIX`] or empty uses the plain node id.
+    pub fn device_id_suffix(mut self, device_id_suffix: Option<String>) -> Self {
+        self.device_id_suffix = device_id_suffix;
+        self
+    }
+
+    pub fn skip_discovery(mut self, skip_discovery: bool) -> Self {
+        self.skip_discovery = skip_discovery;
+        self
+    }
+
+    /// Defaults to three times the `interval`.
+    pub fn expire_after(mut self, expire_after: Option<u32>) -> Self {
+        self.expire_after = expire_after;
+        self
+    }
+
+    pub fn interval(mut self, interval: Option<u32>) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Whether switch commands are received, switches are only discovered when they are.
+    pub fn handle_switches(mut self, handle_switches: bool) -> Self {
+        self.handle_switches = handle_switches;
+        self
+    }
+
+    pub fn collect_concurrency(mut self, collect_concurrency: usize) -> Self {
+        self.collect_concurrency = collect_concurrency;
+        self
+    }
+
+    pub fn component_filter(mut self, component_filter: ComponentFilter) -> Self {
+        self.component_filter = component_filter;
+        self
+    }
+
+    /// Sensors whose id starts with one of these are not built, on top of the config's
+    /// `disabled_prefixes`.
+    pub fn disabled_prefixes(mut self, disabled_prefixes: Vec<String>) -> Self {
+        self.disabled_prefixes = disabled_prefixes;
+        self
+    }
+
+    pub fn availability_topic(mut self, availability_topic: Option<String>) -> Self {
+        self.availability_topic = availability_topic;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn retain_state(mut self, retain_state: bool) -> Self {
+        self.retain_state = retain_state;
+        self
+    }
+
+    pub fn max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    pub fn discovery_qos(mut self, discovery_qos: QoS) -> Self {
+        self.discovery_qos = discovery_qos;
+        self
+    }
+
+    /// Without a retained discovery config Home Assistant only learns about the sensors
+    /// the next time they are published after it (or the broker) restarts.
+    pub fn discovery_retain(mut self, discovery_retain: bool) -> Self {
+        self.discovery_retain = discovery_retain;
+        self
+    }
+
+    /// Decimals of the cpu and memory usage percentages.
+    pub fn precision(mut self, precision: u8) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Unit the temperature sensors are published in.
+    pub fn temp_unit(mut self, temp_unit: TempUnit) -> Self {
+        self.temp_unit = temp_unit;
+        self
+    }
+
+    /// Publish every value in one retained JSON object on this topic instead of a topic per
+    /// sensor, discovery picks each value out with a `value_template`.
+    pub fn json_state_topic(mut self, json_state_topic: Option<String>) -> Self {
+        self.json_state_topic = json_state_topic;
+        self
+    }
+
+    pub fn build(self) -> Result<UnraidStats> {
+        let mut sensor_config = if self.config_files.is_empty() {
+            None
+        } else {
+            Some(config::try_load_configs(&self.config_files)?)
+        };
+        if let Some(config) = self.config {
+            sensor_config
+                .get_or_insert_with(Config::default)
+                .merge(config);
+        }
+
+        let node_id = self
+            .node_id
+            .unwrap_or_else(|| format!("unraid_{}", self.device_name));
+        let device_id_suffix = match self.device_id_suffix.as_deref() {
+            None | Some(MACHINE_ID_SUFFIX) => machine_id_suffix(),
+            Some(NO_DEVICE_ID_SUFFIX) => None,
+            Some(suffix) => Some(suffix.to_string()),
+        };
+        let device_identifier = match device_id_suffix {
+            Some(suffix) if !suffix.is_empty() => format!("{}_{}", node_id, suffix),
+            _ => node_id.clone(),
+        };
 
         Ok(UnraidStats {
-            sensor_config,
-            docker,
-            json_output: args.json_output,
-            discovery_prefix: args.discovery_prefix.clone(),
-            device_name: args.device_name.clone(),
-            skip_discovery: args.skip_discovery,
+            config_files: self.config_files,
+            sensor_config: RwLock::new(sensor_config),
+            docker: self.docker,
+            json_output: self.json_output,
+            discovery_prefix: self.discovery_prefix,
+            node_id,
+            device_identifier,
+            device_name: self.device_name,
+            device_model: self.device_model,
+            device_manufacturer: self.device_manufacturer,
+            device_sw_version: self.device_sw_version,
+            skip_discovery: self.skip_discovery,
+            expire_after: self
+                .expire_after
+                .or(self.interval.map(|interval| interval.saturating_mul(3))),
+            collect_concurrency: self.collect_concurrency,
+            component_filter: self.component_filter,
+            disabled_prefixes: self.disabled_prefixes,
+            availability_topic: self.availability_topic,
+            json_array: self.json_array.then(|| Mutex::new(Vec::new())),
+            dry_run: self.dry_run,
+            retain_state: self.retain_state,
+            max_packet_size: self.max_packet_size,
+            discovery_qos: self.discovery_qos,
+            discovery_retain: self.discovery_retain,
+            precision: self.precision,
+            temp_unit: self.temp_unit,
+            json_state_topic: self.json_state_topic,
+            json_state: Mutex::new(serde_json::Map::new()),
+            published_discovery: Mutex::new(HashMap::new()),
+            unit_warnings: Mutex::new(HashSet::new()),
+            interval: self.interval,
+            handle_switches: self.handle_switches,
+            switch_states: Mutex::new(HashMap::new()),
+            last_collected: Mutex::new(HashMap::new()),
+            cache: SensorCache::default(),
         })
     }
+}
+
+impl UnraidStats {
+    pub fn builder() -> UnraidStatsBuilder {
+        UnraidStatsBuilder::default()
+    }
+
+    pub async fn new(args: &Args) -> Result<Self> {
+        let docker = docker_stats::connect(args.docker_host.as_deref())
+            .inspect_err(|e| warn!("Docker unavailable, skipping docker sensors: {:#}", e))
+            .ok();
+
+        UnraidStats::builder()
+            .config_files(args.config_file.clone())
+            .docker(docker)
+            .json_output(args.json_output)
+            .json_array(args.json_array)
+            .discovery_prefix(&args.discovery_prefix)
+            .device_name(&args.device_name)
+            .node_id(args.node_id())
+            .device_model(&args.device_model)
+            .device_manufacturer(&args.device_manufacturer)
+            .device_sw_version(args.device_sw_version.clone())
+            .device_id_suffix(args.device_id_suffix.clone())
+            .skip_discovery(args.skip_discovery)
+            .expire_after(args.expire_after)
+            .interval(args.interval)
+            .handle_switches(args.handles_switches())
+            .collect_concurrency(args.collect_concurrency)
+            .component_filter(ComponentFilter {
+                include: args.component_include.clone(),
+                exclude: args.component_exclude.clone(),
+            })
+            .disabled_prefixes(args.disabled_prefixes.clone())
+            .availability_topic(args.availability_topic())
+            .dry_run(args.dry_run)
+            .retain_state(args.retain_state)
+            .max_packet_size(args.max_packet_size)
+            .discovery_qos(mqtt_config::qos(args.discovery_qos))
+            .discovery_retain(args.discovery_retain)
+            .precision(args.precision)
+            .temp_unit(args.temp_unit)
+            .json_state_topic(args.json_state_topic.clone())
+            .build()
+    }
+
+    /// Re-reads the config files. On error the current config is kept.
+    pub fn reload_config(&self) -> Result<()> {
+        if self.config_files.is_empty() {
+            return Ok(());
+        }
+        let sensor_config = config::try_load_configs(&self.config_files)?;
+        *self.sensor_config.write().unwrap() = Some(sensor_config);
+        self.clear_cache();
+        Ok(())
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
 
     pub async fn dump_sensors_toml(&self, filename: &PathBuf) -> Result<()> {
         let dump_sensors = self
             .sensors()
             .await
             .into_iter()
-            .map(|s| (s.id.clone(), SensorsDump::SensorOverride(s)))
+            .map(|s| (s.id.clone(), SensorsDump::SensorOverride(s.into())))
             .collect::<HashMap<String, SensorsDump>>();
         let dump = config::ConfigDump {
             sensors: dump_sensors,
@@ -58,46 +479,259 @@ impl UnraidStats {
         Ok(())
     }
 
+    /// Prints the sensors that would be published, as a table or with `json_output` as JSON.
+    pub async fn print_sensor_list(&self) -> Result<()> {
+        let rows = self
+            .sensors()
+            .await
+            .into_iter()
+            .map(|sensor| {
+                [
+                    sensor.id,
+                    sensor.name,
+                    sensor.unit.unwrap_or_default(),
+                    sensor
+                        .reporter
+                        .as_ref()
+                        .map(|r| r.name())
+                        .unwrap_or_default()
+                        .to_string(),
+                    if sensor.disabled { "yes" } else { "no" }.to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        if self.json_output {
+            let sensors = rows
+                .iter()
+                .map(|[id, name, unit, reporter, disabled]| {
+                    json!({
+                        "id": id,
+                        "name": name,
+                        "unit": unit,
+                        "reporter": reporter,
+                        "disabled": disabled == "yes",
+                    })
+                })
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&sensors)?);
+            return Ok(());
+        }
+
+        let header = ["ID", "NAME", "UNIT", "REPORTER", "DISABLED"].map(String::from);
+        let mut widths = header.clone().map(|h| h.len());
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row) {
+                *width = (*width).max(column.chars().count());
+            }
+        }
+        for row in std::iter::once(&header).chain(&rows) {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(column, width)| format!("{:width$}", column, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{}", line.trim_end());
+        }
+        Ok(())
+    }
+
     fn get_device_info(&self) -> serde_json::Value {
         json!({
-            "identifiers": [format!("unraid_{}", self.device_name)],
-            "name": format!("Unraid {}", self.device_name),
-            "model": "Unraid Server",
-            "manufacturer": "Lime Technology",
-            "sw_version": self.get_unraid_version().unwrap_or_else(|_| "Unknown".to_string())
+            "identifiers": [self.device_identifier],
+            "name": self.device_name,
+            "model": self.device_model,
+            "manufacturer": self.device_manufacturer,
+            "sw_version": self.device_sw_version.clone().unwrap_or_else(|| {
+                self.get_unraid_version().unwrap_or_else(|_| "Unknown".to_string())
+            })
         })
     }
 
     fn get_unraid_version(&self) -> Result<String> {
-        let content = fs::read_to_string("/etc/unraid-version")?;
-        for line in content.lines() {
-            if line.starts_with("version=") {
-                return Ok(line
-                    .trim_start_matches("version=")
-                    .trim_matches('"')
-                    .to_string());
+        let content = fs::read_to_string(UNRAID_VERSION_FILE)?;
+        Ok(parse_unraid_version(&content)
+            .version
+            .unwrap_or_else(|| "Unknown".to_string()))
+    }
+
+    /// `--disable` prefixes followed by the config's `disabled_prefixes`.
+    fn disabled_prefixes(&self) -> Vec<String> {
+        let mut disabled_prefixes = self.disabled_prefixes.clone();
+        if let Some(sensor_config) = self.sensor_config.read().unwrap().as_ref() {
+            disabled_prefixes.extend(sensor_config.disabled_prefixes.iter().cloned());
+        }
+        disabled_prefixes
+    }
+
+    /// Fails when Docker can't be pinged, unless the docker sensors are disabled with a
+    /// `docker` prefix.
+    pub async fn check_docker(&self) -> Result<()> {
+        if self
+            .disabled_prefixes()
+            .iter()
+            .any(|prefix| "docker_".starts_with(prefix.as_str()))
+        {
+            return Ok(());
+        }
+        let Some(docker) = &self.docker else {
+            anyhow::bail!("Docker is unavailable");
+        };
+        docker
+            .ping()
+            .await
+            .map_err(|e| anyhow::anyhow!("Docker is unreachable: {}", e))?;
+        info!("Docker is reachable");
+        Ok(())
+    }
+
+    /// Drops the cached containers and sensors, the next [`UnraidStats::sensors`] builds
+    /// them again. Call it between daemon cycles so new containers are picked up.
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    async fn cached_containers(
+        &self,
+        cache: &SensorCache,
+        docker: &Docker,
+    ) -> Vec<ContainerSummary> {
+        if let Some(containers) = cache.containers.lock().unwrap().as_ref() {
+            return containers.clone();
+        }
+        match docker_stats::containers(docker).await {
+            Ok(containers) => {
+                *cache.containers.lock().unwrap() = Some(containers.clone());
+                containers
+            }
+            Err(e) => {
+                warn!("Failed to list containers: {:#}", e);
+                Vec::new()
             }
         }
-        Ok("Unknown".to_string())
     }
 
+    /// The sensors of the current run, built once until [`UnraidStats::clear_cache`].
     pub async fn sensors(&self) -> Vec<Sensor> {
-        let mut containters = container_sensor_list(&self.docker, &self.device_name)
-            .await
-            .unwrap_or_default();
-        let mut docker = docker_stats::sensor_list(&self.docker).await;
+        self.sensors_with(&self.cache).await
+    }
+
+    /// Like [`UnraidStats::sensors`] with a cache the caller owns, e.g. one per Prometheus
+    /// scrape so scrapes don't invalidate the MQTT loop's sensors.
+    pub async fn sensors_with(&self, cache: &SensorCache) -> Vec<Sensor> {
+        if let Some(sensors) = cache.sensors.lock().unwrap().as_ref() {
+            return sensors.clone();
+        }
+        let sensors = self.build_sensors(cache).await;
+        *cache.sensors.lock().unwrap() = Some(sensors.clone());
+        sensors
+    }
 
-        let mut sys = System::new_all();
-        sys.refresh_all();
+    async fn build_sensors(&self, cache: &SensorCache) -> Vec<Sensor> {
+        let disabled_prefixes = self.disabled_prefixes();
+        let mut memory_unit = MemoryUnit::default();
+        let mut spin_state_devices = Vec::new();
+        let mut cpu_temp_labels = Vec::new();
+        let mut container_labels = Vec::new();
+        if let Some(sensor_config) = self.sensor_config.read().unwrap().as_ref() {
+            memory_unit = sensor_config.memory_unit.unwrap_or_default();
+            spin_state_devices = sensor_config.spin_state_devices.clone();
+            cpu_temp_labels = sensor_config.cpu_temp_labels.clone();
+            container_labels = sensor_config.container_labels.clone();
+        }
+        if cpu_temp_labels.is_empty() {
+            cpu_temp_labels = DEFAULT_CPU_TEMP_LABELS
+                .iter()
+                .map(|label| label.to_string())
+                .collect();
+        }
+        // whole groups are skipped before building them, so no docker calls are made
+        let skipped = |id_prefix: &str| {
+            disabled_prefixes
+                .iter()
+                .any(|prefix| id_prefix.starts_with(prefix.as_str()))
+        };
+
+        let (mut containters, mut docker) = match &self.docker {
+            Some(docker) => (
+                if skipped("dockercontainer_") {
+                    Vec::new()
+                } else {
+                    unique_container_sensors(
+                        docker,
+                        self.cached_containers(cache, docker).await,
+                        &container_labels,
+                    )
+                },
+                if skipped("docker_") {
+                    Vec::new()
+                } else {
+                    docker_stats::sensor_list(docker).await
+                },
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+
+        // one scan per build shared by every system sensor, uptime and boot time don't read
+        // it. Only what the sensors use is refreshed, the process list only when it's needed
+        let mut system = System::new();
+        system.refresh_memory();
+        system.refresh_cpu_all();
+        if !(skipped("processes") && skipped("threads")) {
+            system.refresh_processes_specifics(
+                ProcessesToUpdate::All,
+                true,
+                ProcessRefreshKind::nothing(),
+            );
+        }
+        let system = Arc::new(system);
 
         let mut sensors = vec![
             Sensor {
                 id: "cpu_usage".to_string(),
                 name: "CPU Usage".to_string(),
                 unit: Some("%".to_string()),
+                min: Some(0.0),
+                max: Some(100.0),
+                state_class: Some(StateClass::Measurement),
+                reporter: Some(SensorReporterType::System(SystemSensorReporter {
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::CpuUsage(self.precision.into()),
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "cpu_frequency".to_string(),
+                name: "CPU Frequency".to_string(),
+                unit: Some("MHz".to_string()),
+                device_class: Some(DeviceClass::Frequency),
+                state_class: Some(StateClass::Measurement),
                 reporter: Some(SensorReporterType::System(SystemSensorReporter {
-                    system: Arc::new(System::new_all()),
-                    name: SystemSensorReporterStat::CpuUsage,
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::CpuFrequency,
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "processes".to_string(),
+                name: "Processes".to_string(),
+                icon: Some("application-cog".to_string()),
+                state_class: Some(StateClass::Measurement),
+                reporter: Some(SensorReporterType::System(SystemSensorReporter {
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::ProcessCount,
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "threads".to_string(),
+                name: "Threads".to_string(),
+                icon: Some("format-list-bulleted".to_string()),
+                state_class: Some(StateClass::Measurement),
+                reporter: Some(SensorReporterType::System(SystemSensorReporter {
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::ThreadCount,
                 })),
                 ..Default::default()
             },
@@ -105,33 +739,53 @@ impl UnraidStats {
                 id: "memory_usage".to_string(),
                 name: "Memory Usage".to_string(),
                 unit: Some("%".to_string()),
+                min: Some(0.0),
+                max: Some(100.0),
+                state_class: Some(StateClass::Measurement),
                 reporter: Some(SensorReporterType::System(SystemSensorReporter {
-                    system: Arc::new(System::new_all()),
-                    name: SystemSensorReporterStat::MemoryUsage,
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::MemoryUsage(self.precision.into()),
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "memory_pressure".to_string(),
+                name: "Memory Pressure".to_string(),
+                unit: Some("%".to_string()),
+                icon: Some("memory".to_string()),
+                state_class: Some(StateClass::Measurement),
+                reporter: Some(SensorReporterType::File(FileSensorReporter {
+                    path: PathBuf::from("/proc/pressure/memory"),
+                    transform: Arc::new(|s: &str| {
+                        parse_memory_pressure(s).map(|pressure| format!("{:.2}", pressure))
+                    }),
                 })),
                 ..Default::default()
             },
+            swap_rate_sensor("swap_in_rate", "Swap In Rate", "pswpin"),
+            swap_rate_sensor("swap_out_rate", "Swap Out Rate", "pswpout"),
             Sensor {
                 id: "memory_total".to_string(),
                 name: "Memory Total".to_string(),
-                unit: Some("B".to_string()),
+                unit: Some(memory_unit.unit().to_string()),
                 device_class: Some(DeviceClass::DataSize),
                 icon: Some("memory".to_string()),
                 reporter: Some(SensorReporterType::System(SystemSensorReporter {
-                    system: Arc::new(System::new_all()),
-                    name: SystemSensorReporterStat::MemoryTotal,
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::MemoryTotal(memory_unit),
                 })),
                 ..Default::default()
             },
             Sensor {
                 id: "memory_used".to_string(),
                 name: "Memory Used".to_string(),
-                unit: Some("B".to_string()),
+                unit: Some(memory_unit.unit().to_string()),
                 device_class: Some(DeviceClass::DataSize),
                 icon: Some("memory".to_string()),
+                state_class: Some(StateClass::Measurement),
                 reporter: Some(SensorReporterType::System(SystemSensorReporter {
-                    system: Arc::new(System::new_all()),
-                    name: SystemSensorReporterStat::MemoryUsed,
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::MemoryUsed(memory_unit),
                 })),
                 ..Default::default()
             },
@@ -139,16 +793,30 @@ impl UnraidStats {
                 id: "disk_usage".to_string(),
                 name: "Disk Usage".to_string(),
                 unit: Some("%".to_string()),
+                min: Some(0.0),
+                max: Some(100.0),
                 reporter: Some(SensorReporterType::Command(CommandSensorReporter {
                     command: "df".to_string(),
-                    args: Some(vec!["-BM".to_string(), "/mnt/user".to_string()]),
+                    args: Some(vec![
+                        "-P".to_string(),
+                        "-B1".to_string(),
+                        "/mnt/user".to_string(),
+                    ]),
                     transform: Some(Arc::new(|s: &str| {
-                        if let Some(disk_info) = parse_disk_usage(&s) {
-                            Some(format!("{}", disk_info.usage_percent))
-                        } else {
-                            None
-                        }
+                        parse_disk_usage(s).map(|disk_info| format!("{}", disk_info.usage_percent))
+                    })),
+                    attributes: Some(Arc::new(|s: &str| {
+                        parse_disk_usage(s).map(|disk_info| {
+                            json!({
+                                "filesystem": disk_info.filesystem,
+                                "mountpoint": disk_info.mountpoint,
+                                "total": disk_info.total,
+                                "used": disk_info.used,
+                                "available": disk_info.available,
+                            })
+                        })
                     })),
+                    ..Default::default()
                 })),
                 ..Default::default()
             },
@@ -157,18 +825,23 @@ impl UnraidStats {
                 name: "Disk Total".to_string(),
                 unit: Some("B".to_string()),
                 device_class: Some(DeviceClass::DataSize),
-                icon: Some("data_size".to_string()),
+                icon: Some("harddisk".to_string()),
                 reporter: Some(SensorReporterType::Command(CommandSensorReporter {
                     command: "df".to_string(),
-                    args: Some(vec!["/mnt/user".to_string()]),
+                    args: Some(vec![
+                        "-P".to_string(),
+                        "-B1".to_string(),
+                        "/mnt/user".to_string(),
+                    ]),
                     transform: Some(Arc::new(|s: &str| {
-                        if let Some(disk_info) = parse_disk_usage(&s) {
+                        if let Some(disk_info) = parse_disk_usage(s) {
                             debug!("Disk info: {:?}", disk_info);
                             Some(disk_info.total.to_string())
                         } else {
                             None
                         }
                     })),
+                    ..Default::default()
                 })),
                 ..Default::default()
             },
@@ -177,17 +850,88 @@ impl UnraidStats {
                 name: "Disk Available".to_string(),
                 unit: Some("B".to_string()),
                 device_class: Some(DeviceClass::DataSize),
-                icon: Some("data_size".to_string()),
+                icon: Some("harddisk".to_string()),
                 reporter: Some(SensorReporterType::Command(CommandSensorReporter {
                     command: "df".to_string(),
-                    args: Some(vec!["/mnt/user".to_string()]),
+                    args: Some(vec![
+                        "-P".to_string(),
+                        "-B1".to_string(),
+                        "/mnt/user".to_string(),
+                    ]),
                     transform: Some(Arc::new(|s: &str| {
-                        if let Some(disk_info) = parse_disk_usage(&s) {
-                            Some(format!("{}", disk_info.available))
-                        } else {
-                            None
-                        }
+                        parse_disk_usage(s).map(|disk_info| disk_info.available.to_string())
+                    })),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "array_total".to_string(),
+                name: "Array Total".to_string(),
+                unit: Some("B".to_string()),
+                device_class: Some(DeviceClass::DataSize),
+                icon: Some("harddisk".to_string()),
+                reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+                    command: "df".to_string(),
+                    args: Some(vec!["-P".to_string(), "-B1".to_string()]),
+                    transform: Some(Arc::new(|s: &str| {
+                        array_disk_totals(s).map(|(total, _)| total.to_string())
+                    })),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "array_available".to_string(),
+                name: "Array Available".to_string(),
+                unit: Some("B".to_string()),
+                device_class: Some(DeviceClass::DataSize),
+                icon: Some("harddisk".to_string()),
+                reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+                    command: "df".to_string(),
+                    args: Some(vec!["-P".to_string(), "-B1".to_string()]),
+                    transform: Some(Arc::new(|s: &str| {
+                        array_disk_totals(s).map(|(_, available)| available.to_string())
+                    })),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "disk_inodes_used".to_string(),
+                name: "Disk Inodes Used".to_string(),
+                state_class: Some(StateClass::Measurement),
+                icon: Some("file-multiple".to_string()),
+                reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+                    command: "df".to_string(),
+                    args: Some(vec![
+                        "-P".to_string(),
+                        "-i".to_string(),
+                        "/mnt/user".to_string(),
+                    ]),
+                    transform: Some(Arc::new(|s: &str| {
+                        parse_inode_usage(s).map(|inode_info| inode_info.used.to_string())
                     })),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "disk_inodes_free".to_string(),
+                name: "Disk Inodes Free".to_string(),
+                state_class: Some(StateClass::Measurement),
+                icon: Some("file-multiple".to_string()),
+                reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+                    command: "df".to_string(),
+                    args: Some(vec![
+                        "-P".to_string(),
+                        "-i".to_string(),
+                        "/mnt/user".to_string(),
+                    ]),
+                    transform: Some(Arc::new(|s: &str| {
+                        parse_inode_usage(s).map(|inode_info| inode_info.free.to_string())
+                    })),
+                    ..Default::default()
                 })),
                 ..Default::default()
             },
@@ -196,65 +940,224 @@ impl UnraidStats {
                 name: "CPU Temperature".to_string(),
                 unit: Some("°C".to_string()),
                 device_class: Some(DeviceClass::Temperature),
+                state_class: Some(StateClass::Measurement),
                 reporter: Some(SensorReporterType::Command(CommandSensorReporter {
-                    command: "sensor".to_string(),
+                    command: "sensors".to_string(),
                     args: None,
-                    transform: Some(Arc::new(|s: &str| {
-                        if let Some(temp) = parse_cpu_temp(&s) {
-                            Some(format!("{:.1}", temp))
-                        } else {
-                            None
+                    transform: Some(Arc::new({
+                        let labels = cpu_temp_labels.clone();
+                        move |s: &str| {
+                            parse_cpu_temp(s, &labels).map(|(_, temp)| format!("{:.1}", temp))
                         }
                     })),
+                    attributes: Some(Arc::new({
+                        let labels = cpu_temp_labels.clone();
+                        move |s: &str| {
+                            parse_cpu_temp(s, &labels).map(|(label, _)| json!({ "label": label }))
+                        }
+                    })),
+                    ..Default::default()
                 })),
                 ..Default::default()
             },
             Sensor {
                 id: "uptime".to_string(),
                 name: "Uptime".to_string(),
-                icon: Some("duration".to_string()),
+                unit: Some("s".to_string()),
+                device_class: Some(DeviceClass::Duration),
+                icon: Some("clock-outline".to_string()),
+                entity_category: Some(EntityCategory::Diagnostic),
                 reporter: Some(SensorReporterType::System(SystemSensorReporter {
-                    system: Arc::new(System::new_all()),
+                    system: system.clone(),
                     name: SystemSensorReporterStat::Uptime,
                 })),
                 ..Default::default()
             },
+            Sensor {
+                id: "uptime_human".to_string(),
+                name: "Uptime (Formatted)".to_string(),
+                icon: Some("clock-outline".to_string()),
+                entity_category: Some(EntityCategory::Diagnostic),
+                reporter: Some(SensorReporterType::System(SystemSensorReporter {
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::UptimeHuman,
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "boot_time".to_string(),
+                name: "Boot Time".to_string(),
+                device_class: Some(DeviceClass::Timestamp),
+                entity_category: Some(EntityCategory::Diagnostic),
+                reporter: Some(SensorReporterType::System(SystemSensorReporter {
+                    system: system.clone(),
+                    name: SystemSensorReporterStat::BootTime,
+                })),
+                ..Default::default()
+            },
             Sensor {
                 id: "array_status".to_string(),
                 name: "Array Status".to_string(),
                 //Command::new("mdcmd").arg("status")
+                entity_category: Some(EntityCategory::Diagnostic),
                 reporter: Some(SensorReporterType::Command(CommandSensorReporter {
                     command: "mdcmd".to_string(),
                     args: Some(vec!["status".to_string()]),
                     transform: Some(Arc::new(|s: &str| {
-                        if let Some(status) = parse_array_status(&s) {
-                            Some(status)
-                        } else {
-                            None
-                        }
+                        parse_array_status(s).map(|status| array_status_label(&status).to_string())
                     })),
+                    attributes: Some(Arc::new(|s: &str| {
+                        parse_array_status(s).map(|status| json!({ "raw": status }))
+                    })),
+                    ..Default::default()
                 })),
                 ..Default::default()
             },
+            Sensor {
+                id: "array_started".to_string(),
+                name: "Array Started".to_string(),
+                kind: SensorKind::BinarySensor,
+                entity_category: Some(EntityCategory::Diagnostic),
+                reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+                    command: "mdcmd".to_string(),
+                    args: Some(vec!["status".to_string()]),
+                    transform: Some(Arc::new(|s: &str| {
+                        parse_array_status(s).map(|status| {
+                            if status == "STARTED" {
+                                BINARY_SENSOR_ON.to_string()
+                            } else {
+                                BINARY_SENSOR_OFF.to_string()
+                            }
+                        })
+                    })),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            Sensor {
+                id: "stats_collection_duration_ms".to_string(),
+                name: "Stats Collection Duration".to_string(),
+                unit: Some("ms".to_string()),
+                device_class: Some(DeviceClass::Duration),
+                state_class: Some(StateClass::Measurement),
+                entity_category: Some(EntityCategory::Diagnostic),
+                reporter: Some(SensorReporterType::Collection(CollectionStat::DurationMs)),
+                ..Default::default()
+            },
+            Sensor {
+                id: "stats_sensors_total".to_string(),
+                name: "Stats Sensors Collected".to_string(),
+                state_class: Some(StateClass::Measurement),
+                entity_category: Some(EntityCategory::Diagnostic),
+                reporter: Some(SensorReporterType::Collection(CollectionStat::SensorsTotal)),
+                ..Default::default()
+            },
+            Sensor {
+                id: "stats_sensors_failed".to_string(),
+                name: "Stats Sensors Failed".to_string(),
+                icon: Some("alert-circle-outline".to_string()),
+                state_class: Some(StateClass::Measurement),
+                entity_category: Some(EntityCategory::Diagnostic),
+                reporter: Some(SensorReporterType::Collection(
+                    CollectionStat::SensorsFailed,
+                )),
+                ..Default::default()
+            },
+            Sensor {
+                id: "last_seen".to_string(),
+                name: "Last Seen".to_string(),
+                device_class: Some(DeviceClass::Timestamp),
+                icon: Some("clock-check-outline".to_string()),
+                entity_category: Some(EntityCategory::Diagnostic),
+                reporter: Some(SensorReporterType::Collection(CollectionStat::LastSeen)),
+                ..Default::default()
+            },
         ];
 
         sensors.append(&mut containters);
         sensors.append(&mut docker);
+        if !skipped("fan_") {
+            sensors.append(&mut fan_sensor_list().await);
+        }
+        if !skipped("unraid_") {
+            sensors.extend(unraid_version_sensor());
+        }
+        sensors.extend(
+            spin_state_devices
+                .iter()
+                .map(|device| spin_state_sensor(device)),
+        );
+        if let Some(sensor_config) = self.sensor_config.read().unwrap().as_ref() {
+            sensors.extend(
+                sensor_config
+                    .sensors
+                    .values()
+                    .flat_map(|entry| match entry {
+                        Sensors::Command(command_sensor) => vec![Sensor::from(command_sensor)],
+                        Sensors::Static(static_sensor) => vec![Sensor::from(static_sensor)],
+                        Sensors::CommandMulti(multi_sensor) => multi_sensor.sensors(),
+                        Sensors::SensorOverride(_) | Sensors::Switch(_) => Vec::new(),
+                    }),
+            );
+        }
+        if !skipped("component_") || !skipped("disk_") {
+            sensors.append(&mut component_stats::sensor_list(&self.component_filter));
+        }
+        sensors.retain(|sensor| {
+            !disabled_prefixes
+                .iter()
+                .any(|prefix| sensor.id.starts_with(prefix.as_str()))
+        });
 
-        if let Some(sensor_config) = self.sensor_config.as_ref() {
+        let mut device_class_icons = HashMap::new();
+        if let Some(sensor_config) = self.sensor_config.read().unwrap().as_ref() {
             self.apply_sensor_overrides(&mut sensors, sensor_config);
+            device_class_icons = sensor_config.device_class_icons.clone();
         }
+        for sensor in sensors.iter_mut() {
+            if sensor.icon.is_none() {
+                if let Some(device_class) = &sensor.device_class {
+                    sensor.icon = device_class_icons
+                        .get(device_class)
+                        .cloned()
+                        .or_else(|| device_class.default_icon().map(str::to_string));
+                }
+            }
+            // after the overrides, a sensor configured with °F is published as read
+            if sensor.device_class == Some(DeviceClass::Temperature)
+                && sensor.unit.as_deref() == Some(TempUnit::C.unit())
+            {
+                sensor.unit = Some(self.temp_unit.unit().to_string());
+                sensor.temp_unit = self.temp_unit;
+            }
+            if let Some(mismatch) = sensor.unit_mismatch().filter(|_| !sensor.disabled) {
+                let warning = format!("Sensor {}: {}", sensor.id, mismatch);
+                if self.unit_warnings.lock().unwrap().insert(warning.clone()) {
+                    warn!("{}", warning);
+                }
+            }
+            if sensor.expire_after.is_none() {
+                sensor.expire_after = match (sensor.interval, self.interval) {
+                    (Some(sensor_interval), Some(interval)) => {
+                        Some(sensor_interval.max(interval).saturating_mul(3))
+                    }
+                    _ => self.expire_after,
+                };
+            }
+        }
+        // config sensors come from a HashMap, keep output and discovery stable between runs
+        sensors.sort_by(|a, b| a.id.cmp(&b.id));
         sensors
     }
 
-    fn apply_sensor_overrides(&self, sensors: &mut Vec<Sensor>, sensor_config: &Config) {
+    fn apply_sensor_overrides(&self, sensors: &mut [Sensor], sensor_config: &Config) {
         for sensor in sensors.iter_mut() {
             // apply star overrides then named overrides
             let mut star_name = sensor.id.split('_');
             let star_id = format!(
                 "{}_*_{}",
                 star_name.nth(0).unwrap_or(""),
-                star_name.last().unwrap_or("")
+                star_name.next_back().unwrap_or("")
             );
             if let Some(Sensors::SensorOverride(update)) =
                 sensor_config.sensors.get(star_id.as_str())
@@ -270,67 +1173,472 @@ impl UnraidStats {
         }
     }
 
-    #[instrument(level = "trace", skip(self))]
-    pub async fn publish_discovery(&self, client: Option<&AsyncClient>) -> Result<()> {
+    #[instrument(level = "trace", skip(self, sensors))]
+    pub async fn publish_discovery(
+        &self,
+        client: Option<&AsyncClient>,
+        sensors: &[Sensor],
+    ) -> Result<()> {
         if self.skip_discovery {
             return Ok(());
         }
 
         let device_info = self.get_device_info();
-        let node_id = format!("unraid_{}", self.device_name);
+        let node_id = &self.node_id;
 
-        for sensor in self.sensors().await {
+        for sensor in sensors {
             if sensor.disabled {
                 continue;
             }
-            let discovery_topic = sensor.discovery_topic(&self.discovery_prefix, &node_id);
-            let config = sensor.disovery_config(&self.device_name, &node_id, &device_info);
-            self.publish_raw(client, &discovery_topic, config.to_string(), true)
+            let discovery_topic = sensor.discovery_topic(&self.discovery_prefix, node_id);
+            let mut config = sensor.disovery_config(node_id, &device_info);
+            if let Some(json_state_topic) = &self.json_state_topic {
+                config["state_topic"] = json!(json_state_topic);
+                config["value_template"] =
+                    json!(format!("{{{{ value_json.{} }}}}", sensor.object_id()));
+            }
+            if sensor.has_own_availability() {
+                let mut availability = vec![json!({ "topic": sensor.availability_topic(node_id) })];
+                if let Some(availability_topic) = &self.availability_topic {
+                    availability.push(json!({ "topic": availability_topic }));
+                }
+                config["availability"] = json!(availability);
+                config["availability_mode"] = json!("all");
+            } else if let Some(availability_topic) = &self.availability_topic {
+                config["availability_topic"] = json!(availability_topic);
+            }
+            let payload = config.to_string();
+            if !self.discovery_changed(&discovery_topic, &payload) {
+                continue;
+            }
+            self.publish_discovery_config(client, &sensor.id, discovery_topic, payload)
                 .await?;
         }
 
+        // a switch nobody listens to would never respond in Home Assistant
+        let switches = if self.handle_switches {
+            self.switches()
+        } else {
+            Vec::new()
+        };
+        for switch in switches {
+            let discovery_topic = switch.discovery_topic(&self.discovery_prefix, node_id);
+            let mut config = switch.discovery_config(node_id, &device_info);
+            if let Some(availability_topic) = &self.availability_topic {
+                config["availability_topic"] = json!(availability_topic);
+            }
+            let payload = config.to_string();
+            if self.discovery_changed(&discovery_topic, &payload) {
+                self.publish_discovery_config(client, &switch.id, discovery_topic, payload)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Discovery is retained, only entities whose config changed are republished.
+    fn discovery_changed(&self, discovery_topic: &str, payload: &str) -> bool {
+        self.published_discovery
+            .lock()
+            .unwrap()
+            .get(discovery_topic)
+            .is_none_or(|published| published != payload)
+    }
+
+    async fn publish_discovery_config(
+        &self,
+        client: Option<&AsyncClient>,
+        id: &str,
+        discovery_topic: String,
+        payload: String,
+    ) -> Result<()> {
+        let packet_size = mqtt_config::publish_packet_size(&discovery_topic, payload.len());
+        if packet_size > self.max_packet_size {
+            warn!(
+                "Discovery config for {} is {} bytes, over the max packet size of {}. \
+                 Skipping it, raise --max-packet-size to publish it",
+                id, packet_size, self.max_packet_size
+            );
+            return Ok(());
+        }
+        self.publish_raw(
+            client,
+            &discovery_topic,
+            payload.clone(),
+            self.discovery_qos,
+            self.discovery_retain,
+        )
+        .await?;
+        self.published_discovery
+            .lock()
+            .unwrap()
+            .insert(discovery_topic, payload);
         Ok(())
     }
 
-    #[instrument(level = "trace", skip(self))]
-    pub async fn publish_stats(&self, client: Option<&AsyncClient>) -> Result<()> {
-        let node_id = format!("unraid_{}", self.device_name);
+    /// Enabled `type = "switch"` entries of the config, sorted by id.
+    pub fn switches(&self) -> Vec<SwitchConfig> {
+        let mut switches: Vec<SwitchConfig> = self
+            .sensor_config
+            .read()
+            .unwrap()
+            .iter()
+            .flat_map(|sensor_config| sensor_config.sensors.values())
+            .filter_map(|entry| match entry {
+                Sensors::Switch(switch) if !switch.disabled => Some(switch.clone()),
+                _ => None,
+            })
+            .collect();
+        switches.sort_by(|a, b| a.id.cmp(&b.id));
+        switches
+    }
+
+    /// Topics Home Assistant sends switch commands to.
+    pub fn switch_command_topics(&self) -> Vec<String> {
+        self.switches()
+            .iter()
+            .map(|switch| switch.command_topic(&self.node_id))
+            .collect()
+    }
+
+    /// Publishes the last state of every switch, `OFF` before it has been toggled, so Home
+    /// Assistant doesn't show them as unknown. Call it once the command topics are subscribed.
+    pub async fn publish_switch_states(&self, client: Option<&AsyncClient>) {
+        for switch in self.switches() {
+            let state = self
+                .switch_states
+                .lock()
+                .unwrap()
+                .get(&switch.id)
+                .copied()
+                .unwrap_or(BINARY_SENSOR_OFF);
+            self.publish_ha_state(
+                client,
+                &switch.state_topic(&self.node_id),
+                state.to_string(),
+                true,
+            )
+            .await;
+        }
+    }
+
+    /// Runs the switch command a message from Home Assistant asks for and publishes the new
+    /// state back.
+    pub async fn handle_message(&self, client: &AsyncClient, publish: Publish) {
+        let payload = String::from_utf8_lossy(&publish.payload);
+        let Some(switch) = self
+            .switches()
+            .into_iter()
+            .find(|switch| switch.command_topic(&self.node_id) == publish.topic)
+        else {
+            debug!("No handler for message on {}", publish.topic);
+            return;
+        };
+        match switch.run(&payload).await {
+            Ok(state) => {
+                self.switch_states
+                    .lock()
+                    .unwrap()
+                    .insert(switch.id.clone(), state);
+                self.publish_ha_state(
+                    Some(client),
+                    &switch.state_topic(&self.node_id),
+                    state.to_string(),
+                    true,
+                )
+                .await
+            }
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+
+    /// Collects the discovery topics the broker has retained for this device.
+    pub async fn retained_discovery_topics(
+        &self,
+        client: &AsyncClient,
+        eventloop: &mut EventLoop,
+    ) -> Result<Vec<String>> {
+        let node_id = &self.node_id;
+        let filter = format!("{}/+/{}/+/config", self.discovery_prefix, node_id);
+        client.subscribe(&filter, QoS::AtLeastOnce).await?;
+
+        let mut topics = Vec::new();
+        let mut subscribed = false;
+        // retained messages arrive right after the SubAck, stop once the broker goes quiet
+        loop {
+            let wait = if subscribed {
+                Duration::from_secs(1)
+            } else {
+                Duration::from_secs(10)
+            };
+            match tokio::time::timeout(wait, eventloop.poll()).await {
+                Ok(Ok(Event::Incoming(Packet::SubAck(_)))) => subscribed = true,
+                Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    if !publish.payload.is_empty() {
+                        topics.push(publish.topic);
+                    }
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) if subscribed => break,
+                Err(_) => anyhow::bail!("Timed out waiting for subscription to {}", filter),
+            }
+        }
+        client.unsubscribe(&filter).await?;
+        Ok(topics)
+    }
+
+    /// Publishes empty retained discovery payloads so Home Assistant removes the entities.
+    /// With `stale_only` the topics of enabled sensors are left alone.
+    #[instrument(level = "trace", skip(self, client, retained_topics))]
+    pub async fn remove_discovery(
+        &self,
+        client: Option<&AsyncClient>,
+        retained_topics: Vec<String>,
+        stale_only: bool,
+    ) -> Result<()> {
+        let node_id = &self.node_id;
+        let mut topics = retained_topics.into_iter().collect::<BTreeSet<String>>();
+        let mut active = HashSet::new();
         for sensor in self.sensors().await {
-            if sensor.disabled {
+            let discovery_topic = sensor.discovery_topic(&self.discovery_prefix, node_id);
+            if !sensor.disabled {
+                active.insert(discovery_topic.clone());
+            }
+            if !stale_only {
+                topics.insert(discovery_topic);
+            }
+        }
+
+        for switch in self.switches() {
+            let discovery_topic = switch.discovery_topic(&self.discovery_prefix, node_id);
+            active.insert(discovery_topic.clone());
+            if !stale_only {
+                topics.insert(discovery_topic);
+            }
+        }
+
+        for topic in topics {
+            if stale_only && active.contains(&topic) {
                 continue;
             }
-            let sensor_topic = sensor.sensor_topic(&node_id);
-            if let Some(mut source) = sensor.reporter {
-                if let Some(value) = source.get_value().await {
-                    debug!("Sensor ID: {}, Value: {}", sensor.id, value);
-                    self.publish_ha_state(client, &sensor_topic, value).await?;
+            debug!("Removing discovery topic: {}", topic);
+            // always retained, it replaces the retained config
+            self.publish_raw(client, &topic, String::new(), self.discovery_qos, true)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Reads every enabled sensor, at most `collect_concurrency` at a time, without
+    /// publishing anything.
+    pub async fn collect_values(&self) -> Vec<(Sensor, Option<String>)> {
+        self.collect_values_with(&self.cache).await
+    }
+
+    /// [`UnraidStats::collect_values`] of the sensors in `cache`.
+    pub async fn collect_values_with(&self, cache: &SensorCache) -> Vec<(Sensor, Option<String>)> {
+        let mut sensors = self.sensors_with(cache).await;
+        let values = self
+            .collect(sensors.iter_mut().filter(|sensor| !sensor.disabled))
+            .await
+            .into_iter()
+            .map(|reading| reading.value)
+            .collect::<Vec<_>>();
+        sensors
+            .into_iter()
+            .filter(|sensor| !sensor.disabled)
+            .zip(values)
+            .collect()
+    }
+
+    async fn collect<'a>(
+        &self,
+        sensors: impl Iterator<Item = &'a mut Sensor>,
+    ) -> Vec<SensorReading<'a>> {
+        let semaphore = Semaphore::new(self.collect_concurrency.max(1));
+        join_all(sensors.map(|sensor| {
+            let semaphore = &semaphore;
+            async move {
+                let _permit = semaphore.acquire().await;
+                let mut value = None;
+                let mut attributes = None;
+                if let Some(source) = sensor.reporter.as_mut() {
+                    // closes with the sensor's busy/idle time, RUST_LOG=debug shows the slow ones
+                    let span = tracing::debug_span!(
+                        "collect_sensor",
+                        sensor = %sensor.id,
+                        reporter = source.name(),
+                        elapsed_ms = tracing::field::Empty,
+                    );
+                    let started = Instant::now();
+                    let reading = AssertUnwindSafe(source.get_value())
+                        .catch_unwind()
+                        .instrument(span.clone())
+                        .await;
+                    span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+                    drop(span);
+                    // a panicking reporter only loses its own reading
+                    match reading {
+                        Ok(reading) => {
+                            value = reading;
+                            attributes = source.get_attributes();
+                        }
+                        Err(_) => warn!(
+                            "Sensor {} panicked while collecting, skipping it",
+                            sensor.id
+                        ),
+                    }
+                }
+                SensorReading {
+                    value: value
+                        .map(|value| sensor.temp_unit.convert(value))
+                        .and_then(|value| sensor.apply_limits(value)),
+                    sensor,
+                    attributes,
                 }
             }
+        }))
+        .await
+    }
+
+    /// Sensors with their own `interval` are skipped until it has passed since they were
+    /// last collected.
+    fn is_due(&self, sensor: &Sensor, now: Instant) -> bool {
+        let Some(interval) = sensor.interval else {
+            return true;
+        };
+        self.last_collected
+            .lock()
+            .unwrap()
+            .get(&sensor.id)
+            .is_none_or(|last| now.duration_since(*last) >= Duration::from_secs(interval.into()))
+    }
+
+    /// Returns the ids of `critical` sensors that had no value, the rest are still published.
+    #[instrument(level = "trace", skip(self, sensors))]
+    pub async fn publish_stats(
+        &self,
+        client: Option<&AsyncClient>,
+        sensors: &mut [Sensor],
+    ) -> Result<Vec<String>> {
+        let node_id = &self.node_id;
+        let now = Instant::now();
+        let due = sensors.iter_mut().filter(|sensor| {
+            !sensor.disabled
+                && !matches!(sensor.reporter, Some(SensorReporterType::Collection(_)))
+                && self.is_due(sensor, now)
+        });
+        let readings = self.collect(due).await;
+        let duration_ms = now.elapsed().as_millis();
+        let total = readings.len();
+        let failed = readings
+            .iter()
+            .filter(|reading| reading.value.is_none())
+            .count();
+        let critical_failed = readings
+            .iter()
+            .filter(|reading| reading.value.is_none() && reading.sensor.is_critical())
+            .map(|reading| reading.sensor.id.clone())
+            .collect::<Vec<_>>();
+
+        for reading in readings {
+            let sensor = reading.sensor;
+            self.last_collected
+                .lock()
+                .unwrap()
+                .insert(sensor.id.clone(), now);
+            let retain = sensor.retain.unwrap_or(self.retain_state);
+            if sensor.has_own_availability() {
+                let availability = if reading.value.is_some() {
+                    AVAILABILITY_ONLINE
+                } else {
+                    AVAILABILITY_OFFLINE
+                };
+                self.publish_ha_state(
+                    client,
+                    &sensor.availability_topic(node_id),
+                    availability.to_string(),
+                    true,
+                )
+                .await;
+            }
+            if let Some(value) = reading.value {
+                debug!("Sensor ID: {}, Value: {}", sensor.id, value);
+                self.publish_value(client, sensor, value, retain).await;
+            }
+            if let Some(attributes) = reading.attributes {
+                self.publish_ha_state(
+                    client,
+                    &sensor.attributes_topic(node_id),
+                    attributes.to_string(),
+                    retain,
+                )
+                .await;
+            }
         }
 
-        Ok(())
+        for sensor in sensors.iter().filter(|sensor| !sensor.disabled) {
+            let Some(SensorReporterType::Collection(stat)) = &sensor.reporter else {
+                continue;
+            };
+            let value = match stat {
+                CollectionStat::DurationMs => duration_ms.to_string(),
+                CollectionStat::SensorsTotal => total.to_string(),
+                CollectionStat::SensorsFailed => failed.to_string(),
+                CollectionStat::LastSeen => chrono::Utc::now().to_rfc3339(),
+            };
+            let retain = sensor.retain.unwrap_or(self.retain_state);
+            self.publish_value(client, sensor, value, retain).await;
+        }
+
+        if let Some(json_state_topic) = &self.json_state_topic {
+            let state = serde_json::Value::Object(self.json_state.lock().unwrap().clone());
+            self.publish_ha_state(client, json_state_topic, state.to_string(), true)
+                .await;
+        }
+
+        Ok(critical_failed)
     }
 
+    /// With `json_state_topic` the value is only kept for the combined object.
+    async fn publish_value(
+        &self,
+        client: Option<&AsyncClient>,
+        sensor: &Sensor,
+        value: String,
+        retain: bool,
+    ) {
+        if self.json_state_topic.is_some() {
+            self.json_state
+                .lock()
+                .unwrap()
+                .insert(sensor.object_id(), json!(value));
+        } else {
+            self.publish_ha_state(client, &sensor.sensor_topic(&self.node_id), value, retain)
+                .await;
+        }
+    }
+
+    /// Failures are logged instead of returned, one topic that can't be published
+    /// shouldn't stop the rest of the cycle.
     #[instrument(level = "trace", skip(self, client))]
     async fn publish_ha_state(
         &self,
         client: Option<&AsyncClient>,
         topic_suffix: &str,
         value: String,
-    ) -> Result<()> {
-        if self.json_output {
-            println!(
-                "{}",
-                json!({
-                    "topic": topic_suffix,
-                    "payload": value
-                })
-            );
-        } else if let Some(client) = client {
-            self.publish_raw(Some(client), &topic_suffix, value, false)
-                .await?;
+        retain: bool,
+    ) {
+        if self.json_output || client.is_some() {
+            if let Err(e) = self
+                .publish_raw(client, topic_suffix, value, QoS::AtLeastOnce, retain)
+                .await
+            {
+                warn!("Giving up publishing {}: {:#}", topic_suffix, e);
+            }
         }
-        Ok(())
     }
 
     #[instrument(level = "trace", skip(self, client))]
@@ -339,69 +1647,189 @@ impl UnraidStats {
         client: Option<&AsyncClient>,
         topic: &str,
         payload: String,
+        qos: QoS,
         retain: bool,
     ) -> Result<()> {
         if self.json_output {
-            println!(
-                "{}",
-                json!({
-                    "topic": topic,
-                    "payload": payload,
-                })
+            // enough for a bridge to republish it the way it would have been sent
+            self.output_json(json!({
+                "topic": topic,
+                "payload": payload,
+                "qos": qos as u8,
+                "retain": retain,
+            }));
+        } else if self.dry_run {
+            info!(
+                "Dry run, would publish to {} (qos: {:?}, retain: {}): {}",
+                topic, qos, retain, payload
             );
         } else if let Some(client) = client {
-            client
-                .publish(topic, QoS::AtLeastOnce, retain, payload)
-                .await?;
+            // the event loop would fail on it and reconnect, dropping it anyway
+            let packet_size = mqtt_config::publish_packet_size(topic, payload.len());
+            if packet_size > self.max_packet_size {
+                warn!(
+                    "Not publishing {}, {} bytes is over the max packet size of {}",
+                    topic, packet_size, self.max_packet_size
+                );
+                return Ok(());
+            }
+            let mut attempt = 1;
+            let mut delay = PUBLISH_RETRY_DELAY;
+            loop {
+                match client.publish(topic, qos, retain, payload.clone()).await {
+                    Ok(()) => break,
+                    Err(e) if attempt < PUBLISH_ATTEMPTS => {
+                        warn!(
+                            "Publishing {} failed, retrying in {:?}: {}",
+                            topic, delay, e
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;
+                        attempt += 1;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
         }
         Ok(())
     }
-}
 
-#[derive(Debug)]
-struct DiskInfo {
-    total: String,
-    available: String,
-    usage_percent: f64,
-}
+    fn output_json(&self, record: serde_json::Value) {
+        match &self.json_array {
+            Some(records) => records.lock().unwrap().push(record),
+            None => println!("{}", record),
+        }
+    }
 
-fn parse_disk_usage(df_output: &str) -> Option<DiskInfo> {
-    df_output.lines().skip(1).next().and_then(|line| {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 5 {
-            let usage_str = parts[4].trim_end_matches('%');
-
-            let usage_percent = usage_str.parse::<f64>().ok()?;
-            Some(DiskInfo {
-                total: parts[1].to_string(),
-                available: parts[3].to_string(),
-                usage_percent,
-            })
-        } else {
-            None
+    /// Prints the records buffered by `--json-array` as a single JSON array.
+    pub fn flush_json_output(&self) -> Result<()> {
+        if self.json_array.is_some() {
+            let records = self.take_json_records();
+            println!("{}", serde_json::to_string_pretty(&records)?);
         }
-    })
+        Ok(())
+    }
+
+    /// The `{topic, payload, qos, retain}` records buffered by `--json-array` since the
+    /// last flush.
+    pub fn take_json_records(&self) -> Vec<serde_json::Value> {
+        self.json_array
+            .as_ref()
+            .map(|records| std::mem::take(&mut *records.lock().unwrap()))
+            .unwrap_or_default()
+    }
 }
 
-fn parse_cpu_temp(sensors_output: &str) -> Option<f64> {
-    sensors_output
-        .lines()
-        .find(|line| line.contains("Package id 0"))
-        .and_then(|line| {
-            line.split_whitespace()
-                .find(|word| word.contains("°C"))
-                .and_then(|temp| {
-                    temp.trim_start_matches('+')
-                        .trim_end_matches("°C")
-                        .parse::<f64>()
-                        .ok()
+/// Binary sensor that is on while the disk is spinning. `hdparm -C` only asks the drive for
+/// its power mode (CHECK POWER MODE), unlike reading SMART data it doesn't spin up a disk
+/// in standby.
+pub fn spin_state_sensor(device: &str) -> Sensor {
+    let name = device.trim_start_matches("/dev/");
+    Sensor {
+        id: format!("disk_{}_active", config::sanitize_id(name)),
+        name: format!("Disk {} Active", name),
+        kind: SensorKind::BinarySensor,
+        icon: Some("harddisk".to_string()),
+        reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+            command: "hdparm".to_string(),
+            args: Some(vec!["-C".to_string(), format!("/dev/{}", name)]),
+            transform: Some(Arc::new(|s: &str| {
+                parse_spin_state(s).map(|active| {
+                    if active {
+                        BINARY_SENSOR_ON.to_string()
+                    } else {
+                        BINARY_SENSOR_OFF.to_string()
+                    }
                 })
+            })),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+/// One sensor for every fan `sensors` reports when the sensors are built, each read runs
+/// `sensors` again and picks its fan out.
+async fn fan_sensor_list() -> Vec<Sensor> {
+    let output = match tokio::process::Command::new("sensors").output().await {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(e) => {
+            debug!("Not adding fan sensors, sensors failed: {}", e);
+            return Vec::new();
+        }
+    };
+    parse_fan_speeds(&output)
+        .into_iter()
+        .map(|(number, _)| Sensor {
+            id: format!("fan_{}_rpm", number),
+            name: format!("Fan {} Speed", number),
+            unit: Some("RPM".to_string()),
+            icon: Some("fan".to_string()),
+            state_class: Some(StateClass::Measurement),
+            reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+                command: "sensors".to_string(),
+                args: None,
+                transform: Some(Arc::new(move |s: &str| {
+                    parse_fan_speeds(s)
+                        .into_iter()
+                        .find(|(fan, _)| *fan == number)
+                        .map(|(_, rpm)| rpm.to_string())
+                })),
+                ..Default::default()
+            })),
+            ..Default::default()
         })
+        .collect()
+}
+
+/// Pages swapped per second, sustained swapping warns of an OOM long before swap is full.
+fn swap_rate_sensor(id: &str, name: &str, counter: &'static str) -> Sensor {
+    Sensor {
+        id: id.to_string(),
+        name: name.to_string(),
+        unit: Some("pages/s".to_string()),
+        icon: Some("swap-horizontal".to_string()),
+        state_class: Some(StateClass::Measurement),
+        reporter: Some(SensorReporterType::FileRate(FileRateReporter {
+            path: PathBuf::from("/proc/vmstat"),
+            sample: Duration::from_secs(1),
+            counter: Arc::new(move |s: &str| parse_vmstat(s).get(counter).copied()),
+        })),
+        ..Default::default()
+    }
 }
 
-fn parse_array_status(status_output: &str) -> Option<String> {
-    status_output
-        .lines()
-        .find(|line| line.starts_with("mdState="))
-        .map(|line| line.trim_start_matches("mdState=").to_string())
+const UNRAID_VERSION_FILE: &str = "/etc/unraid-version";
+
+/// Diagnostic sensor with the Unraid version, the other keys of the version file are its
+/// attributes. `None` when not running on Unraid.
+fn unraid_version_sensor() -> Option<Sensor> {
+    let unraid_version = parse_unraid_version(&fs::read_to_string(UNRAID_VERSION_FILE).ok()?);
+    Some(Sensor {
+        id: "unraid_version".to_string(),
+        name: "Unraid Version".to_string(),
+        icon: Some("information-outline".to_string()),
+        entity_category: Some(EntityCategory::Diagnostic),
+        reporter: Some(SensorReporterType::Static(StaticSensorReporter {
+            value: unraid_version.version?,
+            attributes: Some(json!(unraid_version.extra)),
+        })),
+        ..Default::default()
+    })
+}
+
+/// `--device-id-suffix` value that appends [`machine_id_suffix`], the default.
+pub const MACHINE_ID_SUFFIX: &str = "machine-id";
+
+/// `--device-id-suffix` value that keeps the plain node id as the device identifier.
+pub const NO_DEVICE_ID_SUFFIX: &str = "none";
+
+/// `/etc/machine-id`, or the hostname when there is none, so two servers with the same
+/// device name don't end up as one Home Assistant device.
+pub fn machine_id_suffix() -> Option<String> {
+    fs::read_to_string("/etc/machine-id")
+        .ok()
+        .map(|machine_id| machine_id.trim().to_string())
+        .filter(|machine_id| !machine_id.is_empty())
+        .or_else(|| System::host_name().map(|host_name| config::sanitize_id(&host_name)))
 }
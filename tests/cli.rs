@@ -0,0 +1,160 @@
+//! Tests for command line argument parsing and how runs of the binary end
+
+use clap::Parser;
+use std::time::{Duration, Instant};
+use unraid_mqtt_stats::cli::Args;
+
+#[test]
+fn test_once_conflicts_with_interval() {
+    assert!(Args::try_parse_from(["unraid-mqtt-stats", "--once"]).is_ok());
+    let err = Args::try_parse_from(["unraid-mqtt-stats", "--once", "--interval", "30"])
+        .expect_err("--once and --interval should conflict");
+    assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+}
+
+#[test]
+fn test_discovery_qos_and_retain() {
+    let args = Args::try_parse_from(["unraid-mqtt-stats"]).unwrap();
+    assert_eq!(args.discovery_qos, 1);
+    assert!(args.discovery_retain);
+    let args = Args::try_parse_from([
+        "unraid-mqtt-stats",
+        "--discovery-qos",
+        "0",
+        "--discovery-retain",
+        "false",
+    ])
+    .unwrap();
+    assert_eq!(args.discovery_qos, 0);
+    assert!(!args.discovery_retain);
+    assert!(Args::try_parse_from(["unraid-mqtt-stats", "--discovery-qos", "3"]).is_err());
+}
+
+#[test]
+fn test_no_color() {
+    let args = Args::try_parse_from(["unraid-mqtt-stats", "--no-color"]).unwrap();
+    assert!(!args.log_colors());
+}
+
+#[test]
+fn test_availability_flags() {
+    let parse = |extra: &[&str]| {
+        let mut args = vec!["unraid-mqtt-stats"];
+        args.extend(extra);
+        Args::try_parse_from(args).unwrap().availability_topic()
+    };
+    assert_eq!(parse(&[]), None);
+    assert_eq!(
+        parse(&["--interval", "30"]).as_deref(),
+        Some("unraid_unraid/availability")
+    );
+    assert_eq!(parse(&["--interval", "30", "--no-availability"]), None);
+    assert!(parse(&["--interval", "30", "--no-availability", "--availability"]).is_some());
+    assert_eq!(
+        parse(&["--availability"]).as_deref(),
+        Some("unraid_unraid/availability")
+    );
+    assert_eq!(parse(&["--availability", "--no-availability"]), None);
+    assert_eq!(parse(&["--availability", "--dry-run"]), None);
+}
+
+/// Port nothing listens on, it was just released.
+fn closed_port() -> String {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+        .to_string()
+}
+
+fn run_against_closed_port(extra_args: &[&str]) -> std::process::Child {
+    std::process::Command::new(env!("CARGO_BIN_EXE_unraid-mqtt-stats"))
+        .args([
+            "--host",
+            "127.0.0.1",
+            "--port",
+            &closed_port(),
+            "--disable",
+            "docker",
+        ])
+        .args(extra_args)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+/// Waits up to `limit` for the process to exit, killing it otherwise.
+fn wait_with_limit(
+    child: &mut std::process::Child,
+    limit: Duration,
+) -> Option<std::process::ExitStatus> {
+    let started = Instant::now();
+    while started.elapsed() < limit {
+        if let Some(status) = child.try_wait().unwrap() {
+            return Some(status);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    None
+}
+
+#[test]
+fn test_one_shot_unreachable_broker_exits_with_error() {
+    let mut child = run_against_closed_port(&[]);
+    let status = wait_with_limit(&mut child, Duration::from_secs(60))
+        .expect("one-shot run kept going without a broker");
+    assert!(!status.success());
+}
+
+#[test]
+fn test_daemon_unreachable_broker_keeps_retrying() {
+    let mut child = run_against_closed_port(&["--interval", "30"]);
+    assert_eq!(wait_with_limit(&mut child, Duration::from_secs(5)), None);
+}
+
+#[test]
+fn test_generate_completion() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_unraid-mqtt-stats"))
+        .args(["--generate-completion", "bash"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("unraid-mqtt-stats"));
+    assert!(script.contains("--device-name"));
+    assert!(Args::try_parse_from(["unraid-mqtt-stats", "--generate-completion", "ksh"]).is_err());
+}
+
+#[test]
+fn test_unit_mismatch_warned_in_every_mode() {
+    let config = std::env::temp_dir().join(format!("unraid_mismatch_{}.toml", std::process::id()));
+    std::fs::write(
+        &config,
+        "[sensors.cpu_usage]\ntype = \"override\"\ndevice_class = \"temperature\"\ndisabled = false\n",
+    )
+    .unwrap();
+    for mode in ["--list-sensors", "--json-output"] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_unraid-mqtt-stats"))
+            .args([mode, "--disable", "docker", "-c"])
+            .arg(&config)
+            .env("RUST_LOG", "warn")
+            .env("NO_COLOR", "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(
+            stdout
+                .matches("Sensor cpu_usage: unit % is not valid for device_class temperature")
+                .count(),
+            1,
+            "{}: {}",
+            mode,
+            stdout
+        );
+    }
+    std::fs::remove_file(&config).unwrap();
+}
@@ -0,0 +1,54 @@
+use unraid_mqtt_stats::component_stats::{component_sensors, is_drive, label_id, ComponentFilter};
+use unraid_mqtt_stats::config::SensorReporterType;
+
+#[test]
+fn test_label_id() {
+    assert_eq!(
+        label_id("nvme Composite WD_BLACK SN850X"),
+        "nvme_composite_wd_black_sn850x"
+    );
+    assert_eq!(label_id("coretemp Package id 0"), "coretemp_package_id_0");
+}
+
+#[test]
+fn test_component_filter() {
+    let filter = ComponentFilter {
+        include: vec!["nvme".to_string(), "Chipset".to_string()],
+        exclude: vec!["sensor 2".to_string()],
+    };
+    assert!(filter.matches("nvme Composite"));
+    assert!(filter.matches("chipset temp"));
+    assert!(!filter.matches("nvme Sensor 2"));
+    assert!(!filter.matches("coretemp Core 0"));
+    assert!(ComponentFilter::default().matches("anything"));
+}
+
+#[test]
+fn test_is_drive() {
+    assert!(is_drive("nvme Composite WD_BLACK SN850X"));
+    assert!(is_drive("drivetemp"));
+    assert!(!is_drive("coretemp Package id 0"));
+}
+
+#[test]
+fn test_component_sensors_shared_label() {
+    let sensors = component_sensors(&["nvme Composite", "coretemp Core 0", "nvme Composite"]);
+    let ids: Vec<&str> = sensors.iter().map(|sensor| sensor.id.as_str()).collect();
+    assert_eq!(
+        ids,
+        vec![
+            "disk_nvme_composite_1_temp",
+            "component_coretemp_core_0_temp",
+            "disk_nvme_composite_2_temp"
+        ]
+    );
+    let indexes: Vec<usize> = sensors
+        .iter()
+        .map(|sensor| match &sensor.reporter {
+            Some(SensorReporterType::Component(reporter)) => reporter.index,
+            _ => panic!("Expected a component reporter"),
+        })
+        .collect();
+    assert_eq!(indexes, vec![0, 0, 1]);
+    assert_eq!(sensors[2].name, "Disk nvme Composite Temperature 2");
+}
@@ -1,9 +1,11 @@
 //! Tests for parsing and generating config TOML (ignoring reporter fields)
 
+use serde_json::json;
 use std::collections::HashMap;
-use toml;
 use unraid_mqtt_stats::config::{
-    Config, ConfigDump, DeviceClass, Sensor, SensorConfig, Sensors, SensorsDump,
+    format_duration_human, icon_name, load_config, sanitize_id, CommandSensorReporter, Config,
+    ConfigDump, DeviceClass, MemoryUnit, OutOfRange, Sensor, SensorKind, SensorReporterType,
+    Sensors, SensorsDump, StateClass,
 };
 
 fn example_toml() -> &'static str {
@@ -60,15 +62,18 @@ fn test_generate_config_dump() {
     let mut sensors = HashMap::new();
     sensors.insert(
         "temp_sensor".to_string(),
-        SensorsDump::SensorOverride(Sensor {
-            id: "temp_sensor".to_string(),
-            name: "Temperature".to_string(),
-            unit: Some("°C".to_string()),
-            device_class: Some(DeviceClass::Temperature),
-            icon: Some("mdi:thermometer".to_string()),
-            disabled: false,
-            reporter: None,
-        }),
+        SensorsDump::SensorOverride(
+            Sensor {
+                id: "temp_sensor".to_string(),
+                name: "Temperature".to_string(),
+                unit: Some("°C".to_string()),
+                device_class: Some(DeviceClass::Temperature),
+                icon: Some("mdi:thermometer".to_string()),
+                disabled: false,
+                ..Default::default()
+            }
+            .into(),
+        ),
     );
     let config_dump = ConfigDump { sensors };
     let toml_str = toml::to_string(&config_dump).expect("Failed to serialize ConfigDump");
@@ -93,3 +98,379 @@ disabled = false
     assert!(toml_out.contains("temp_sensor"));
     assert!(toml_out.contains("Temperature"));
 }
+
+#[test]
+fn test_invalid_device_class_suggests_closest() {
+    let toml_str = r#"
+[sensors.temp_sensor]
+type = "override"
+device_class = "temprature"
+disabled = false
+"#;
+    let err = toml::from_str::<Config>(toml_str).expect_err("device_class should be rejected");
+    let message = err.to_string();
+    assert!(message.contains("did you mean `temperature`?"));
+    assert!(message.contains("data_size"));
+}
+
+#[test]
+fn test_device_class_all_round_trips() {
+    for device_class in DeviceClass::ALL {
+        let value = serde_json::to_value(device_class).unwrap();
+        assert_eq!(
+            &serde_json::from_value::<DeviceClass>(value).unwrap(),
+            device_class
+        );
+    }
+    // the schema lists the variants serde knows about
+    let schema = unraid_mqtt_stats::schema::config_schema();
+    let variants = schema["$defs"]["DeviceClass"]["enum"].as_array().unwrap();
+    assert_eq!(variants.len(), DeviceClass::ALL.len());
+    for (variant, device_class) in variants.iter().zip(DeviceClass::ALL) {
+        assert_eq!(variant.as_str().unwrap(), device_class.as_str());
+    }
+}
+
+#[test]
+fn test_parse_state_class() {
+    let toml_str = r#"
+[sensors.power]
+type = "command"
+name = "Power"
+unit = "W"
+device_class = "power"
+state_class = "measurement"
+command = "cat"
+disabled = false
+"#;
+    let config: Config = toml::from_str(toml_str).expect("Failed to parse config TOML");
+    match &config.sensors["power"] {
+        Sensors::Command(cs) => {
+            assert_eq!(cs.state_class, Some(StateClass::Measurement));
+            let sensor = Sensor::from(cs);
+            let discovery = sensor.disovery_config("unraid_unraid", &json!({}));
+            assert_eq!(discovery["state_class"], "measurement");
+        }
+        _ => panic!("Expected Command"),
+    }
+}
+
+#[test]
+fn test_load_json_config() {
+    let path = std::env::temp_dir().join(format!("unraid_sensors_{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"sensors": {"temp_sensor": {"type": "override", "icon": "thermometer", "disabled": true}}}"#,
+    )
+    .unwrap();
+    let config = load_config(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    match &config.sensors["temp_sensor"] {
+        Sensors::SensorOverride(sc) => {
+            assert_eq!(sc.id, "temp_sensor");
+            assert_eq!(sc.icon.as_deref(), Some("thermometer"));
+            assert!(sc.disabled);
+        }
+        _ => panic!("Expected SensorOverride"),
+    }
+}
+
+#[test]
+fn test_load_yaml_config() {
+    let path = std::env::temp_dir().join(format!("unraid_sensors_{}.yaml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+memory_unit: GiB
+sensors:
+  temp_sensor:
+    type: override
+    icon: thermometer
+    disabled: true
+  uptime_check:
+    type: command
+    name: Uptime Check
+    command: uptime
+    args: ["-p"]
+    disabled: false
+"#,
+    )
+    .unwrap();
+    let config = load_config(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.memory_unit, Some(MemoryUnit::GiB));
+    match &config.sensors["temp_sensor"] {
+        Sensors::SensorOverride(sc) => {
+            assert_eq!(sc.id, "temp_sensor");
+            assert_eq!(sc.icon.as_deref(), Some("thermometer"));
+            assert!(sc.disabled);
+        }
+        _ => panic!("Expected SensorOverride"),
+    }
+    match &config.sensors["uptime_check"] {
+        Sensors::Command(cs) => {
+            assert_eq!(cs.command, "uptime");
+            assert_eq!(cs.args.as_deref(), Some(&["-p".to_string()][..]));
+        }
+        _ => panic!("Expected Command"),
+    }
+}
+
+#[test]
+fn test_format_duration_human() {
+    assert_eq!(format_duration_human(0), "0d 0h 0m");
+    assert_eq!(format_duration_human(90061), "1d 1h 1m");
+    assert_eq!(format_duration_human(3599), "0d 0h 59m");
+}
+
+#[test]
+fn test_sanitize_id() {
+    assert_eq!(
+        sanitize_id("dockercontainer_Plex-Media.Server_cpu"),
+        "dockercontainer_plex-media_server_cpu"
+    );
+    assert_eq!(sanitize_id("cpu_usage"), "cpu_usage");
+}
+
+#[test]
+fn test_discovery_config_ids() {
+    let sensor = Sensor {
+        id: "cpu_usage".to_string(),
+        name: "CPU Usage".to_string(),
+        ..Default::default()
+    };
+    let config = sensor.disovery_config("unraid_unraid", &json!({}));
+    assert_eq!(config["name"], "CPU Usage");
+    assert_eq!(config["has_entity_name"], true);
+    assert_eq!(config["unique_id"], "unraid_unraid_cpu_usage");
+    // the default node id isn't repeated in the entity id, sensor.unraid_cpu_usage
+    assert_eq!(config["object_id"], "unraid_cpu_usage");
+    assert_eq!(
+        config["state_topic"],
+        "unraid_unraid/sensor/cpu_usage/state"
+    );
+
+    for (node_id, object_id) in [
+        ("unraid_arrakis", "unraid_arrakis_cpu_usage"),
+        ("tower", "tower_cpu_usage"),
+    ] {
+        let config = sensor.disovery_config(node_id, &json!({}));
+        assert_eq!(config["unique_id"], format!("{}_cpu_usage", node_id));
+        assert_eq!(config["object_id"], object_id);
+    }
+}
+
+#[test]
+fn test_extra_discovery() {
+    let toml_str = r#"
+[sensors.cpu_usage]
+type = "override"
+disabled = false
+extra_discovery = { suggested_area = "Server Closet", icon = "mdi:chip" }
+"#;
+    let config: Config = toml::from_str(toml_str).expect("Failed to parse config TOML");
+    let Sensors::SensorOverride(sensor_config) = &config.sensors["cpu_usage"] else {
+        panic!("Expected SensorOverride");
+    };
+    let mut sensor = Sensor {
+        id: "cpu_usage".to_string(),
+        name: "CPU Usage".to_string(),
+        icon: Some("cpu-64-bit".to_string()),
+        ..Default::default()
+    };
+    sensor.merge(sensor_config);
+    let discovery = sensor.disovery_config("unraid_unraid", &json!({}));
+    assert_eq!(discovery["suggested_area"], "Server Closet");
+    assert_eq!(discovery["icon"], "mdi:chip");
+}
+
+#[test]
+fn test_unit_mismatch() {
+    let mut sensor = Sensor {
+        id: "disk".to_string(),
+        unit: Some("%".to_string()),
+        device_class: Some(DeviceClass::DataSize),
+        ..Default::default()
+    };
+    assert!(sensor
+        .unit_mismatch()
+        .unwrap()
+        .contains("not valid for device_class data_size"));
+    sensor.unit = Some("GiB".to_string());
+    assert_eq!(sensor.unit_mismatch(), None);
+    sensor.device_class = Some(DeviceClass::Timestamp);
+    assert!(sensor.unit_mismatch().is_some());
+    sensor.unit = None;
+    assert_eq!(sensor.unit_mismatch(), None);
+}
+
+#[test]
+fn test_disabled_prefixes_merge() {
+    let mut config: Config = toml::from_str(
+        r#"
+disabled_prefixes = ["docker"]
+
+[sensors.cpu_usage]
+type = "override"
+disabled = false
+"#,
+    )
+    .expect("Failed to parse config TOML");
+    let other: Config =
+        toml::from_str("disabled_prefixes = [\"disk_\"]\n[sensors]\n").expect("Failed to parse");
+    config.merge(other);
+    assert_eq!(config.disabled_prefixes, vec!["docker", "disk_"]);
+    assert_eq!(config.sensors.len(), 1);
+}
+
+#[test]
+fn test_memory_unit() {
+    let config: Config =
+        toml::from_str("memory_unit = \"GiB\"\n[sensors]\n").expect("Failed to parse");
+    assert_eq!(config.memory_unit, Some(MemoryUnit::GiB));
+    assert_eq!(MemoryUnit::GiB.format(3 << 29), "1.50");
+    assert_eq!(MemoryUnit::GB.format(1_500_000_000), "1.50");
+    assert_eq!(MemoryUnit::B.format(1024), "1024");
+}
+
+#[test]
+fn test_dump_includes_command() {
+    let sensor = Sensor {
+        id: "array_status".to_string(),
+        name: "Array Status".to_string(),
+        reporter: Some(SensorReporterType::Command(CommandSensorReporter {
+            command: "mdcmd".to_string(),
+            args: Some(vec!["status".to_string()]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let mut sensors = HashMap::new();
+    sensors.insert(
+        "array_status".to_string(),
+        SensorsDump::SensorOverride(sensor.into()),
+    );
+    let toml_str = toml::to_string(&ConfigDump { sensors }).expect("Failed to serialize");
+    assert!(toml_str.contains("command = \"mdcmd\""));
+    assert!(toml_str.contains("args = [\"status\"]"));
+
+    // the dump is a valid config, the command override is the same command
+    let config: Config = toml::from_str(&toml_str).expect("Failed to parse dump as config");
+    let Sensors::SensorOverride(sensor_config) = &config.sensors["array_status"] else {
+        panic!("Expected SensorOverride");
+    };
+    assert_eq!(sensor_config.command.as_deref(), Some("mdcmd"));
+}
+
+#[test]
+fn test_power_command_sensor_discovery() {
+    let toml_str = r#"
+[sensors.ups_power]
+type = "command"
+name = "UPS Power"
+unit = "W"
+device_class = "power"
+state_class = "measurement"
+command = "/boot/config/scripts/ups_watts.sh"
+post_process = "ParseFloat"
+disabled = false
+
+[sensors.ups_energy]
+type = "command"
+name = "UPS Energy"
+unit = "kWh"
+device_class = "energy"
+state_class = "total_increasing"
+command = "/boot/config/scripts/ups_kwh.sh"
+post_process = "ParseFloat"
+disabled = false
+"#;
+    let config: Config = toml::from_str(toml_str).expect("Failed to parse config TOML");
+    let Sensors::Command(power) = &config.sensors["ups_power"] else {
+        panic!("Expected Command");
+    };
+    let power = Sensor::from(power);
+    assert_eq!(power.unit_mismatch(), None);
+    let discovery = power.disovery_config("unraid_unraid", &json!({}));
+    assert_eq!(discovery["device_class"], "power");
+    assert_eq!(discovery["state_class"], "measurement");
+    assert_eq!(discovery["unit_of_measurement"], "W");
+
+    let Sensors::Command(energy) = &config.sensors["ups_energy"] else {
+        panic!("Expected Command");
+    };
+    let energy = Sensor::from(energy);
+    assert_eq!(energy.unit_mismatch(), None);
+    let discovery = energy.disovery_config("unraid_unraid", &json!({}));
+    assert_eq!(discovery["device_class"], "energy");
+    assert_eq!(discovery["state_class"], "total_increasing");
+}
+
+#[test]
+fn test_apply_limits() {
+    let mut sensor = Sensor {
+        id: "cpu_usage".to_string(),
+        min: Some(0.0),
+        max: Some(100.0),
+        ..Default::default()
+    };
+    assert_eq!(
+        sensor.apply_limits("42.5".to_string()).as_deref(),
+        Some("42.5")
+    );
+    assert_eq!(
+        sensor.apply_limits("104.2".to_string()).as_deref(),
+        Some("100.0")
+    );
+    assert_eq!(sensor.apply_limits("-3".to_string()).as_deref(), Some("0"));
+    assert_eq!(
+        sensor.apply_limits("n/a".to_string()).as_deref(),
+        Some("n/a")
+    );
+    sensor.out_of_range = Some(OutOfRange::Drop);
+    assert_eq!(sensor.apply_limits("104.2".to_string()), None);
+}
+
+#[test]
+fn test_icon_name() {
+    assert_eq!(icon_name("harddisk"), "mdi:harddisk");
+    assert_eq!(icon_name("mdi:cpu-64-bit"), "mdi:cpu-64-bit");
+    let sensor = Sensor {
+        id: "dockercontainer_plex_cpu".to_string(),
+        icon: Some("mdi:cpu-64-bit".to_string()),
+        ..Default::default()
+    };
+    let discovery = sensor.disovery_config("unraid_unraid", &json!({}));
+    assert_eq!(discovery["icon"], "mdi:cpu-64-bit");
+}
+
+#[test]
+fn test_override_component() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.array_started]
+type = "override"
+component = "binary_sensor"
+disabled = false
+"#,
+    )
+    .expect("Failed to parse config TOML");
+    let Sensors::SensorOverride(sensor_config) = &config.sensors["array_started"] else {
+        panic!("Expected SensorOverride");
+    };
+    let mut sensor = Sensor {
+        id: "array_started".to_string(),
+        name: "Array Started".to_string(),
+        ..Default::default()
+    };
+    sensor.merge(sensor_config);
+    assert_eq!(sensor.kind, SensorKind::BinarySensor);
+    assert_eq!(
+        sensor.sensor_topic("unraid_unraid"),
+        "unraid_unraid/binary_sensor/array_started/state"
+    );
+    let discovery = sensor.disovery_config("unraid_unraid", &json!({}));
+    assert_eq!(discovery["payload_on"], "ON");
+}
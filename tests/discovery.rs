@@ -0,0 +1,59 @@
+//! Golden-file tests for the Home Assistant discovery payloads
+
+use serde_json::{json, Value};
+use unraid_mqtt_stats::config::{Sensor, SensorKind, StateClass};
+
+fn device_info() -> Value {
+    json!({
+        "identifiers": ["unraid_unraid"],
+        "name": "unraid",
+        "model": "Unraid Server",
+        "manufacturer": "Lime Technology",
+        "sw_version": "6.12.10"
+    })
+}
+
+/// The origin carries the crate version, pin it so the fixtures don't change on every release.
+fn assert_matches_fixture(mut discovery: Value, fixture: &str) {
+    assert_eq!(discovery["origin"]["sw_version"], env!("CARGO_PKG_VERSION"));
+    discovery["origin"]["sw_version"] = json!("VERSION");
+    let expected: Value = serde_json::from_str(fixture).expect("fixture is valid JSON");
+    assert_eq!(
+        discovery,
+        expected,
+        "discovery payload changed:\n{}",
+        serde_json::to_string_pretty(&discovery).unwrap()
+    );
+}
+
+#[test]
+fn test_sensor_discovery_snapshot() {
+    let sensor = Sensor {
+        id: "cpu_usage".to_string(),
+        name: "CPU Usage".to_string(),
+        unit: Some("%".to_string()),
+        state_class: Some(StateClass::Measurement),
+        icon: Some("cpu-64-bit".to_string()),
+        expire_after: Some(180),
+        ..Default::default()
+    };
+    assert_matches_fixture(
+        sensor.disovery_config("unraid_unraid", &device_info()),
+        include_str!("fixtures/discovery_cpu_usage.json"),
+    );
+}
+
+#[test]
+fn test_binary_sensor_discovery_snapshot() {
+    let sensor = Sensor {
+        id: "disk_sdb_active".to_string(),
+        name: "Disk sdb Active".to_string(),
+        kind: SensorKind::BinarySensor,
+        icon: Some("harddisk".to_string()),
+        ..Default::default()
+    };
+    assert_matches_fixture(
+        sensor.disovery_config("unraid_unraid", &device_info()),
+        include_str!("fixtures/discovery_disk_active.json"),
+    );
+}
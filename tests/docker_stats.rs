@@ -0,0 +1,105 @@
+use bollard::secret::{
+    ContainerSummary, ContainerSummaryStateEnum, ImageSummary, SystemDataUsageResponse, Volume,
+    VolumeUsageData,
+};
+use bollard::API_DEFAULT_VERSION;
+use serde_json::json;
+use std::collections::HashMap;
+use unraid_mqtt_stats::docker_stats::{
+    container_sensors, reclaimable_size, unique_container_sensors,
+};
+
+#[test]
+fn test_reclaimable_size() {
+    let usage = SystemDataUsageResponse {
+        images: Some(vec![
+            ImageSummary {
+                size: 1000,
+                shared_size: 200,
+                containers: 0,
+                ..Default::default()
+            },
+            ImageSummary {
+                size: 5000,
+                containers: 1,
+                ..Default::default()
+            },
+        ]),
+        containers: Some(vec![
+            ContainerSummary {
+                size_rw: Some(30),
+                state: Some(ContainerSummaryStateEnum::EXITED),
+                ..Default::default()
+            },
+            ContainerSummary {
+                size_rw: Some(70),
+                state: Some(ContainerSummaryStateEnum::RUNNING),
+                ..Default::default()
+            },
+        ]),
+        volumes: Some(vec![Volume {
+            usage_data: Some(VolumeUsageData {
+                size: 5,
+                ref_count: 0,
+            }),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    assert_eq!(reclaimable_size(&usage), 800 + 30 + 5);
+}
+
+#[test]
+fn test_duplicate_container_ids() {
+    let docker =
+        bollard::Docker::connect_with_http("http://127.0.0.1:2375", 1, API_DEFAULT_VERSION)
+            .unwrap();
+    let container = |id: &str, name: &str| ContainerSummary {
+        id: Some(id.to_string()),
+        names: Some(vec![name.to_string()]),
+        ..Default::default()
+    };
+    let sensors = unique_container_sensors(
+        &docker,
+        vec![
+            container("0123456789abcdef", "/Plex.Server"),
+            container("fedcba9876543210", "/plex_server"),
+            container("aaaaaaaaaaaaaaaa", "/sonarr"),
+        ],
+        &[],
+    );
+    let ids: Vec<&str> = sensors.iter().map(|sensor| sensor.id.as_str()).collect();
+    assert!(ids.contains(&"dockercontainer_Plex.Server_0123456789ab_cpu"));
+    assert!(ids.contains(&"dockercontainer_plex_server_fedcba987654_cpu"));
+    assert!(ids.contains(&"dockercontainer_sonarr_cpu"));
+}
+
+#[test]
+fn test_container_label_attributes() {
+    let docker =
+        bollard::Docker::connect_with_http("http://127.0.0.1:2375", 1, API_DEFAULT_VERSION)
+            .unwrap();
+    let container = ContainerSummary {
+        id: Some("0123456789abcdef".to_string()),
+        names: Some(vec!["/plex".to_string()]),
+        labels: Some(HashMap::from([
+            (
+                "net.unraid.docker.icon".to_string(),
+                "https://example.com/plex.png".to_string(),
+            ),
+            ("other".to_string(), "hidden".to_string()),
+        ])),
+        ..Default::default()
+    };
+    let labels = ["net.unraid.docker.icon".to_string(), "missing".to_string()];
+    let sensors = container_sensors(&docker, container.clone(), &labels);
+    let reporter = sensors[0].reporter.as_ref().unwrap();
+    assert!(reporter.has_attributes());
+    assert_eq!(
+        reporter.get_attributes(),
+        Some(json!({ "net.unraid.docker.icon": "https://example.com/plex.png" }))
+    );
+
+    let sensors = container_sensors(&docker, container, &[]);
+    assert!(!sensors[0].reporter.as_ref().unwrap().has_attributes());
+}
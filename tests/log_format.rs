@@ -0,0 +1,46 @@
+//! The json log lines have to stay parseable whatever ends up in a field
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt;
+use unraid_mqtt_stats::log_format::JsonFormat;
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_json_format_escapes_field_values() {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let subscriber = fmt()
+        .event_format(JsonFormat)
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            output = "say \"hi\"\nsecond line",
+            "message with \"quotes\"\nand a newline"
+        );
+    });
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(line["level"], "INFO");
+    assert_eq!(line["fields"]["output"], "say \"hi\"\nsecond line");
+    assert_eq!(
+        line["fields"]["message"],
+        "message with \"quotes\"\nand a newline"
+    );
+}
@@ -0,0 +1,93 @@
+use rumqttc::{Publish, QoS};
+use std::time::Duration;
+use tokio::time::timeout;
+use unraid_mqtt_stats::mqtt_config::{
+    flush, publish_packet_size, read_secret_file, run_eventloop, Dispatcher, MqttConfig,
+};
+
+#[test]
+fn test_publish_packet_size_matches_rumqttc() {
+    for payload_len in [0, 100, 200, 20_000] {
+        let topic = "homeassistant/sensor/unraid_unraid/cpu_usage/config";
+        let mut publish = Publish::new(topic, QoS::AtLeastOnce, vec![b'x'; payload_len]);
+        publish.pkid = 1;
+        assert_eq!(publish_packet_size(topic, payload_len), publish.size());
+    }
+}
+
+#[test]
+fn test_read_secret_file() {
+    let path = std::env::temp_dir().join(format!("unraid_mqtt_password_{}", std::process::id()));
+    std::fs::write(&path, "s3cret pass\n").unwrap();
+    let secret = read_secret_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(secret.unwrap(), "s3cret pass");
+    assert!(read_secret_file(&path).is_err());
+}
+
+#[test]
+fn test_dispatcher_routes_by_topic() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut dispatcher = Dispatcher::default();
+    dispatcher.route("unraid_unraid/switch/plex/set".to_string(), sender);
+
+    assert!(dispatcher.dispatch(Publish::new(
+        "unraid_unraid/switch/plex/set",
+        QoS::AtLeastOnce,
+        "ON"
+    )));
+    assert!(!dispatcher.dispatch(Publish::new(
+        "unraid_unraid/switch/other/set",
+        QoS::AtLeastOnce,
+        "ON"
+    )));
+    let publish = receiver.try_recv().unwrap();
+    assert_eq!(&publish.payload[..], b"ON");
+    assert!(receiver.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_eventloop_without_reconnect_fails() {
+    // nothing listens on a port that was just released
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mqtt = MqttConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        client_id: "unraid-mqtt-stats-test".to_string(),
+        username: String::new(),
+        password: String::new(),
+        availability_topic: None,
+        max_packet_size: 10 * 1024,
+    };
+    let (client, eventloop) = mqtt.create_mqtt_client().unwrap();
+    let eventloop = tokio::spawn(run_eventloop(
+        eventloop,
+        client.clone(),
+        None,
+        Dispatcher::default(),
+        false,
+    ));
+    // more than the request channel holds, none of them may block once the loop is gone
+    let published = timeout(Duration::from_secs(10), async {
+        for _ in 0..20 {
+            let _ = client
+                .publish(
+                    "unraid_unraid/sensor/cpu_usage/state",
+                    QoS::AtLeastOnce,
+                    false,
+                    "1",
+                )
+                .await;
+        }
+    })
+    .await;
+    assert!(published.is_ok());
+    let flushed = timeout(Duration::from_secs(10), flush(&client, eventloop))
+        .await
+        .unwrap();
+    assert!(flushed.is_err());
+}
@@ -0,0 +1,212 @@
+//! Parser tests, fixtures are output captured from real servers.
+
+use bollard::secret::ContainerStatsResponse;
+use unraid_mqtt_stats::parsers::{
+    array_disk_totals, array_status_label, calculate_cpu_percent, is_array_disk,
+    parse_array_status, parse_cpu_temp, parse_disk_usage, parse_disk_usage_all, parse_fan_speeds,
+    parse_inode_usage, parse_memory_pressure, parse_spin_state, parse_unraid_version, parse_vmstat,
+    DEFAULT_CPU_TEMP_LABELS,
+};
+
+#[test]
+fn test_parse_disk_usage_posix() {
+    let output = "Filesystem        1-blocks          Used     Available Capacity Mounted on\n\
+                  shfs      20000588955648 5000147238912 15000441716736      25% /mnt/user\n";
+    let disk_info = parse_disk_usage(output).unwrap();
+    assert_eq!(disk_info.filesystem, "shfs");
+    assert_eq!(disk_info.total, 20000588955648);
+    assert_eq!(disk_info.used, 5000147238912);
+    assert_eq!(disk_info.available, 15000441716736);
+    assert_eq!(disk_info.usage_percent, 25.0);
+    assert_eq!(disk_info.mountpoint, "/mnt/user");
+}
+
+#[test]
+fn test_parse_disk_usage_wrapped() {
+    let output = "Filesystem     1B-blocks       Used  Available Use% Mounted on\n\
+                  tank/appdata/a-very-long-dataset-name\n\
+                  \x20              1000000000  250000000  750000000  25% /mnt/user\n";
+    let disk_info = parse_disk_usage(output).unwrap();
+    assert_eq!(
+        disk_info.filesystem,
+        "tank/appdata/a-very-long-dataset-name"
+    );
+    assert_eq!(disk_info.total, 1000000000);
+    assert_eq!(disk_info.available, 750000000);
+    assert_eq!(disk_info.mountpoint, "/mnt/user");
+}
+
+#[test]
+fn test_array_status_label() {
+    assert_eq!(array_status_label("STARTED"), "Started");
+    assert_eq!(array_status_label("ERROR:TOO_MANY_MISSING_DISKS"), "Error");
+    assert_eq!(array_status_label("RECON_DISK"), "RECON_DISK");
+}
+
+#[test]
+fn test_array_disk_totals() {
+    let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+                  /dev/md1p1            1000       400       600      40% /mnt/disk1\n\
+                  /dev/md2p1            2000       500      1500      25% /mnt/disk2\n\
+                  /dev/nvme0n1p1         500       100       400      20% /mnt/cache\n\
+                  shfs                  3500      1000      2500      29% /mnt/user\n";
+    assert_eq!(array_disk_totals(output), Some((3000, 2100)));
+    assert_eq!(
+        array_disk_totals("Filesystem 1024-blocks Used Available Capacity Mounted on\n"),
+        None
+    );
+    assert!(is_array_disk("/mnt/disk12"));
+    assert!(!is_array_disk("/mnt/disks"));
+    assert!(!is_array_disk("/mnt/disk"));
+}
+
+#[test]
+fn test_parse_fan_speeds() {
+    let output = include_str!("fixtures/sensors.txt");
+    assert_eq!(parse_fan_speeds(output), vec![(1, 1205), (2, 850), (3, 0)]);
+}
+
+#[test]
+fn test_parse_cpu_temp_labels() {
+    let labels = vec!["Tctl".to_string(), "Package id 0".to_string()];
+    let intel = include_str!("fixtures/sensors.txt");
+    assert_eq!(
+        parse_cpu_temp(intel, &labels),
+        Some(("Package id 0".to_string(), 42.0))
+    );
+
+    let ryzen =
+        "k10temp-pci-00c3\nAdapter: PCI adapter\nTctl:         +51.3°C\nTccd1:        +47.0°C\n";
+    assert_eq!(
+        parse_cpu_temp(ryzen, &labels),
+        Some(("Tctl".to_string(), 51.3))
+    );
+    assert_eq!(parse_cpu_temp(ryzen, &["CPU Temp".to_string()]), None);
+}
+
+#[test]
+fn test_parse_unraid_version() {
+    let unraid_version = parse_unraid_version("version=\"7.0.1\"\nbuild_date=\"2025-03-01\"\n");
+    assert_eq!(unraid_version.version.as_deref(), Some("7.0.1"));
+    assert_eq!(
+        unraid_version.extra.get("build_date").map(String::as_str),
+        Some("2025-03-01")
+    );
+    assert_eq!(parse_unraid_version("").version, None);
+}
+
+#[test]
+fn test_parse_vmstat() {
+    let counters = parse_vmstat(include_str!("fixtures/vmstat.txt"));
+    assert_eq!(counters.get("pswpin"), Some(&1204));
+    assert_eq!(counters.get("pswpout"), Some(&58812));
+}
+
+#[test]
+fn test_parse_memory_pressure() {
+    let pressure = "some avg10=1.52 avg60=0.30 avg300=0.07 total=445276\nfull avg10=0.80 avg60=0.10 avg300=0.02 total=212904\n";
+    assert_eq!(parse_memory_pressure(pressure), Some(1.52));
+    assert_eq!(parse_memory_pressure(""), None);
+}
+
+#[test]
+fn test_parse_spin_state() {
+    assert_eq!(
+        parse_spin_state("\n/dev/sdb:\n drive state is:  active/idle\n"),
+        Some(true)
+    );
+    assert_eq!(
+        parse_spin_state("\n/dev/sdb:\n drive state is:  standby\n"),
+        Some(false)
+    );
+    assert_eq!(
+        parse_spin_state("\n/dev/sdb:\n drive state is:  unknown\n"),
+        None
+    );
+}
+
+#[test]
+fn test_parse_inode_usage() {
+    let output = "Filesystem       Inodes  IUsed    IFree IUse% Mounted on\n\
+                  /dev/nvme0n1p1 61054976 412345 60642631    1% /mnt/cache\n";
+    let inode_info = parse_inode_usage(output).unwrap();
+    assert_eq!(inode_info.filesystem, "/dev/nvme0n1p1");
+    assert_eq!(inode_info.total, 61054976);
+    assert_eq!(inode_info.used, 412345);
+    assert_eq!(inode_info.free, 60642631);
+    assert_eq!(inode_info.mountpoint, "/mnt/cache");
+}
+
+#[test]
+fn test_parse_disk_usage_all() {
+    let disks = parse_disk_usage_all(include_str!("fixtures/df_mnt.txt"));
+    let mountpoints: Vec<&str> = disks.iter().map(|disk| disk.mountpoint.as_str()).collect();
+    assert_eq!(
+        mountpoints,
+        vec![
+            "/mnt/disk1",
+            "/mnt/disk2",
+            "/mnt/cache",
+            "/mnt/user",
+            "/mnt/disks/Backup Drive"
+        ]
+    );
+    assert_eq!(disks[2].filesystem, "/dev/nvme0n1p1");
+    assert_eq!(disks[2].usage_percent, 38.0);
+    assert_eq!(
+        array_disk_totals(include_str!("fixtures/df_mnt.txt")),
+        Some((15998753308672, 9918247165952))
+    );
+}
+
+#[test]
+fn test_parse_cpu_temp_ryzen() {
+    let labels: Vec<String> = DEFAULT_CPU_TEMP_LABELS
+        .iter()
+        .map(|label| label.to_string())
+        .collect();
+    assert_eq!(
+        parse_cpu_temp(include_str!("fixtures/sensors_ryzen.txt"), &labels),
+        Some(("Tctl".to_string(), 55.6))
+    );
+}
+
+#[test]
+fn test_parse_array_status() {
+    assert_eq!(
+        parse_array_status(include_str!("fixtures/mdcmd_status.txt")).as_deref(),
+        Some("STARTED")
+    );
+    assert_eq!(parse_array_status("sbName=/boot/config/super.dat\n"), None);
+}
+
+#[test]
+fn test_calculate_cpu_percent() {
+    let stats: ContainerStatsResponse =
+        serde_json::from_str(include_str!("fixtures/docker_stats.json")).unwrap();
+    assert_eq!(calculate_cpu_percent(&stats), 40.0);
+
+    // the first sample of a stream has no precpu_stats
+    let first = ContainerStatsResponse {
+        cpu_stats: stats.cpu_stats.clone(),
+        ..Default::default()
+    };
+    assert!(calculate_cpu_percent(&first) > 0.0);
+    assert_eq!(
+        calculate_cpu_percent(&ContainerStatsResponse::default()),
+        0.0
+    );
+}
+
+#[test]
+fn test_calculate_cpu_percent_counter_reset() {
+    let stats: ContainerStatsResponse =
+        serde_json::from_str(include_str!("fixtures/docker_stats.json")).unwrap();
+    // precpu ahead of cpu, the container restarted between the samples
+    let reset = ContainerStatsResponse {
+        cpu_stats: stats.precpu_stats.clone(),
+        precpu_stats: stats.cpu_stats.clone(),
+        ..Default::default()
+    };
+    assert_eq!(calculate_cpu_percent(&reset), 0.0);
+}
@@ -0,0 +1,56 @@
+//! Tests for the Prometheus text exposition output
+
+use unraid_mqtt_stats::config::{Sensor, SensorKind};
+use unraid_mqtt_stats::prometheus::format_metrics;
+
+#[test]
+fn test_format_metrics() {
+    let cpu = Sensor {
+        id: "cpu_usage".to_string(),
+        name: "CPU Usage".to_string(),
+        ..Default::default()
+    };
+    let array = Sensor {
+        id: "array_started".to_string(),
+        name: "Array Started".to_string(),
+        kind: SensorKind::BinarySensor,
+        ..Default::default()
+    };
+    let status = Sensor {
+        id: "array_status".to_string(),
+        name: "Array Status".to_string(),
+        ..Default::default()
+    };
+    let values = vec![
+        (cpu, Some("12.5".to_string())),
+        (array, Some("ON".to_string())),
+        (status, Some("STARTED".to_string())),
+    ];
+
+    let output = format_metrics("tower", &values);
+    assert_eq!(
+        output,
+        "# HELP unraid_cpu_usage CPU Usage\n\
+         # TYPE unraid_cpu_usage gauge\n\
+         unraid_cpu_usage{device=\"tower\"} 12.5\n\
+         # HELP unraid_array_started Array Started\n\
+         # TYPE unraid_array_started gauge\n\
+         unraid_array_started{device=\"tower\"} 1\n"
+    );
+}
+
+#[test]
+fn test_format_metrics_escapes_help_and_labels() {
+    let sensor = Sensor {
+        id: "nas_temp".to_string(),
+        name: "Temp C:\\nas\nrack".to_string(),
+        ..Default::default()
+    };
+    let output = format_metrics("tower \"2\"\n", &[(sensor, Some("40".to_string()))]);
+    assert_eq!(
+        output,
+        "# HELP unraid_nas_temp Temp C:\\\\nas\\nrack\n\
+         # TYPE unraid_nas_temp gauge\n\
+         unraid_nas_temp{device=\"tower \\\"2\\\"\\n\"} 40\n"
+    );
+}
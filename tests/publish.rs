@@ -0,0 +1,258 @@
+//! Publishes through a real MQTT connection to a mock broker and checks what it received.
+
+use bytes::BytesMut;
+use rumqttc::mqttbytes::{v4::read, Error};
+use rumqttc::{
+    ConnAck, ConnectReturnCode, Packet, PingResp, PubAck, PubComp, PubRec, Publish, QoS, SubAck,
+    SubscribeReasonCode,
+};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use unraid_mqtt_stats::config::Config;
+use unraid_mqtt_stats::mqtt_config::{flush, run_eventloop, Dispatcher, MqttConfig};
+use unraid_mqtt_stats::unraid_stats::UnraidStats;
+
+const MAX_PACKET_SIZE: usize = 10 * 1024;
+
+/// Accepts one client and acknowledges what it sends. Resolves to every publish in the
+/// order it arrived once the client disconnects.
+async fn mock_broker() -> (u16, tokio::task::JoinHandle<Vec<Publish>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let broker = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut publishes = Vec::new();
+        let mut buffer = BytesMut::new();
+        loop {
+            let packet = match read(&mut buffer, MAX_PACKET_SIZE) {
+                Ok(packet) => packet,
+                Err(Error::InsufficientBytes(_)) => {
+                    if stream.read_buf(&mut buffer).await.unwrap() == 0 {
+                        return publishes;
+                    }
+                    continue;
+                }
+                Err(e) => panic!("Invalid packet from client: {:?}", e),
+            };
+            let mut reply = BytesMut::new();
+            match packet {
+                Packet::Connect(_) => {
+                    ConnAck::new(ConnectReturnCode::Success, false)
+                        .write(&mut reply)
+                        .unwrap();
+                }
+                Packet::Publish(publish) => {
+                    match publish.qos {
+                        QoS::AtMostOnce => {}
+                        QoS::AtLeastOnce => {
+                            PubAck::new(publish.pkid).write(&mut reply).unwrap();
+                        }
+                        QoS::ExactlyOnce => {
+                            PubRec::new(publish.pkid).write(&mut reply).unwrap();
+                        }
+                    }
+                    publishes.push(publish);
+                }
+                Packet::PubRel(pubrel) => {
+                    PubComp::new(pubrel.pkid).write(&mut reply).unwrap();
+                }
+                Packet::Subscribe(subscribe) => {
+                    let return_codes = subscribe
+                        .filters
+                        .iter()
+                        .map(|filter| SubscribeReasonCode::Success(filter.qos))
+                        .collect();
+                    SubAck::new(subscribe.pkid, return_codes)
+                        .write(&mut reply)
+                        .unwrap();
+                }
+                Packet::PingReq => {
+                    PingResp.write(&mut reply).unwrap();
+                }
+                Packet::Disconnect => return publishes,
+                packet => panic!("Unexpected packet from client: {:?}", packet),
+            }
+            write(&mut stream, &reply).await;
+        }
+    });
+    (port, broker)
+}
+
+/// Acks can fail, the client doesn't wait for them before it disconnects. Everything it
+/// sent is still in the read buffer.
+async fn write(stream: &mut TcpStream, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        let _ = stream.write_all(bytes).await;
+    }
+}
+
+fn payload(publishes: &[Publish], topic: &str) -> String {
+    let publish = publishes
+        .iter()
+        .find(|publish| publish.topic == topic)
+        .unwrap_or_else(|| panic!("Nothing published to {}", topic));
+    String::from_utf8(publish.payload.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_publish_discovery_and_stats() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.rack_location]
+type = "static"
+name = "Rack Location"
+value = "Basement"
+attributes = { owner = "sam" }
+disabled = false
+
+[sensors.rack_units]
+type = "static"
+name = "Rack Units"
+value = "4"
+unit = "U"
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder()
+        .config(config)
+        .node_id("tower".to_string())
+        .build()
+        .unwrap();
+    let mut sensors = stats.sensors().await;
+    sensors.retain(|sensor| sensor.id.starts_with("rack_"));
+
+    let (port, broker) = mock_broker().await;
+    let mqtt = MqttConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        client_id: "unraid-mqtt-stats-test".to_string(),
+        username: String::new(),
+        password: String::new(),
+        availability_topic: None,
+        max_packet_size: MAX_PACKET_SIZE,
+    };
+    let (client, eventloop) = mqtt.create_mqtt_client().unwrap();
+    let eventloop = tokio::spawn(run_eventloop(
+        eventloop,
+        client.clone(),
+        None,
+        Dispatcher::default(),
+        false,
+    ));
+    stats
+        .publish_discovery(Some(&client), &sensors)
+        .await
+        .unwrap();
+    let critical_failed = stats
+        .publish_stats(Some(&client), &mut sensors)
+        .await
+        .unwrap();
+    assert!(critical_failed.is_empty());
+    flush(&client, eventloop).await.unwrap();
+    let publishes = broker.await.unwrap();
+
+    let discovery: Value = serde_json::from_str(&payload(
+        &publishes,
+        "homeassistant/sensor/tower/rack_units/config",
+    ))
+    .unwrap();
+    assert_eq!(discovery["state_topic"], "tower/sensor/rack_units/state");
+    assert_eq!(discovery["unit_of_measurement"], "U");
+    let discovery_publish = publishes
+        .iter()
+        .find(|publish| publish.topic.ends_with("/config"))
+        .unwrap();
+    assert!(discovery_publish.retain);
+    assert_eq!(discovery_publish.qos, QoS::AtLeastOnce);
+
+    assert_eq!(payload(&publishes, "tower/sensor/rack_units/state"), "4");
+    assert_eq!(
+        payload(&publishes, "tower/sensor/rack_location/state"),
+        "Basement"
+    );
+    let attributes: Value = serde_json::from_str(&payload(
+        &publishes,
+        "tower/sensor/rack_location/attributes",
+    ))
+    .unwrap();
+    assert_eq!(attributes, json!({ "owner": "sam" }));
+    // discovery goes out before the first state
+    let first_state = publishes
+        .iter()
+        .position(|publish| publish.topic.ends_with("/state"))
+        .unwrap();
+    assert!(publishes[..first_state]
+        .iter()
+        .all(|publish| publish.topic.ends_with("/config")));
+}
+
+#[tokio::test]
+async fn test_switch_state_published_once_subscribed() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.plex]
+type = "switch"
+name = "Plex"
+command_on = "true"
+command_off = "true"
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder()
+        .config(config)
+        .node_id("tower".to_string())
+        .handle_switches(true)
+        .build()
+        .unwrap();
+
+    let (port, broker) = mock_broker().await;
+    let mqtt = MqttConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        client_id: "unraid-mqtt-stats-test".to_string(),
+        username: String::new(),
+        password: String::new(),
+        availability_topic: None,
+        max_packet_size: MAX_PACKET_SIZE,
+    };
+    let (client, eventloop) = mqtt.create_mqtt_client().unwrap();
+    let mut dispatcher = Dispatcher::default();
+    let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+    for topic in stats.switch_command_topics() {
+        dispatcher.route(topic, sender.clone());
+    }
+    let (subscribed_sender, mut subscribed) = tokio::sync::mpsc::unbounded_channel();
+    dispatcher.on_subscribed(subscribed_sender);
+    let eventloop = tokio::spawn(run_eventloop(
+        eventloop,
+        client.clone(),
+        None,
+        dispatcher,
+        false,
+    ));
+    tokio::time::timeout(Duration::from_secs(10), subscribed.recv())
+        .await
+        .expect("subscriptions acknowledged")
+        .unwrap();
+    stats.publish_switch_states(Some(&client)).await;
+    stats.publish_discovery(Some(&client), &[]).await.unwrap();
+    flush(&client, eventloop).await.unwrap();
+    let publishes = broker.await.unwrap();
+
+    let state = publishes
+        .iter()
+        .find(|publish| publish.topic == "tower/switch/plex/state")
+        .expect("initial switch state");
+    assert_eq!(&state.payload[..], b"OFF");
+    assert!(state.retain);
+    let discovery: Value = serde_json::from_str(&payload(
+        &publishes,
+        "homeassistant/switch/tower/plex/config",
+    ))
+    .unwrap();
+    assert_eq!(discovery["command_topic"], "tower/switch/plex/set");
+}
@@ -0,0 +1,80 @@
+//! The derived schema has to accept the example config and list the fields of every sensor type
+
+use serde_json::Value;
+use std::collections::BTreeSet;
+use unraid_mqtt_stats::config::{
+    CommandMultiSensor, CommandSensor, SensorConfig, Sensors, StaticSensor, SwitchConfig,
+};
+use unraid_mqtt_stats::schema::config_schema;
+
+/// The properties of the `type` variant, with those of the struct it refers to.
+fn sensor_properties(schema: &Value, sensor_type: &str) -> BTreeSet<String> {
+    let variant = schema["$defs"]["Sensors"]["oneOf"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|variant| variant["properties"]["type"]["const"] == sensor_type)
+        .unwrap_or_else(|| panic!("type `{}` missing from schema", sensor_type));
+    let reference = variant["$ref"].as_str().unwrap();
+    let definition = &schema["$defs"][reference.trim_start_matches("#/$defs/")];
+    [variant, definition]
+        .iter()
+        .flat_map(|schema| schema["properties"].as_object().unwrap().keys().cloned())
+        .collect()
+}
+
+#[test]
+fn test_schema_covers_example_config() {
+    let schema = config_schema();
+    let example: toml::Value =
+        toml::from_str(include_str!("../example_sensors.toml")).expect("example config parses");
+
+    for key in example.as_table().unwrap().keys() {
+        assert!(
+            schema["properties"].get(key).is_some(),
+            "top level `{}` missing from schema",
+            key
+        );
+    }
+
+    for (id, sensor) in example["sensors"].as_table().unwrap() {
+        let sensor_type = sensor["type"].as_str().unwrap();
+        let properties = sensor_properties(&schema, sensor_type);
+        for key in sensor.as_table().unwrap().keys() {
+            assert!(
+                properties.contains(key),
+                "`{}` of sensor `{}` missing from the {} schema",
+                key,
+                id,
+                sensor_type
+            );
+        }
+    }
+}
+
+#[test]
+fn test_schema_properties_match_sensor_fields() {
+    let schema = config_schema();
+    let sensors = [
+        Sensors::SensorOverride(SensorConfig::default()),
+        Sensors::Command(CommandSensor::default()),
+        Sensors::Static(StaticSensor::default()),
+        Sensors::CommandMulti(CommandMultiSensor::default()),
+        Sensors::Switch(SwitchConfig::default()),
+    ];
+    for sensor in sensors {
+        let Value::Object(mut fields) = serde_json::to_value(&sensor).unwrap() else {
+            panic!("sensors serialize as objects");
+        };
+        // the id is the key of the entry, never read from the config
+        fields.remove("id");
+        let sensor_type = fields["type"].as_str().unwrap().to_string();
+        assert_eq!(
+            sensor_properties(&schema, &sensor_type),
+            fields.keys().cloned().collect::<BTreeSet<_>>(),
+            "{} schema",
+            sensor_type
+        );
+    }
+    assert!(!sensor_properties(&schema, "command").contains("component"));
+}
@@ -0,0 +1,36 @@
+//! Tests for Home Assistant switches that run commands
+
+use serde_json::json;
+use unraid_mqtt_stats::config::SwitchConfig;
+
+fn switch() -> SwitchConfig {
+    SwitchConfig {
+        id: "plex".to_string(),
+        name: "Plex".to_string(),
+        command_on: "true".to_string(),
+        command_off: "false".to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_switch_discovery_config() {
+    let switch = switch();
+    assert_eq!(
+        switch.discovery_topic("homeassistant", "unraid_unraid"),
+        "homeassistant/switch/unraid_unraid/plex/config"
+    );
+    let config = switch.discovery_config("unraid_unraid", &json!({}));
+    assert_eq!(config["command_topic"], "unraid_unraid/switch/plex/set");
+    assert_eq!(config["state_topic"], "unraid_unraid/switch/plex/state");
+    assert_eq!(config["unique_id"], "unraid_unraid_plex");
+}
+
+#[tokio::test]
+async fn test_switch_run() {
+    let switch = switch();
+    assert_eq!(switch.run("ON").await.unwrap(), "ON");
+    // `false` exits non-zero, the state must not change
+    assert!(switch.run("OFF").await.is_err());
+    assert!(switch.run("TOGGLE").await.is_err());
+}
@@ -0,0 +1,506 @@
+use serde_json::json;
+use unraid_mqtt_stats::config::{Config, SensorReporterType, TempUnit};
+use unraid_mqtt_stats::unraid_stats::{
+    machine_id_suffix, spin_state_sensor, SensorCache, UnraidStats,
+};
+
+#[test]
+fn test_spin_state_sensor_uses_check_power_mode() {
+    let sensor = spin_state_sensor("/dev/sdb");
+    assert_eq!(sensor.id, "disk_sdb_active");
+    let Some(SensorReporterType::Command(reporter)) = &sensor.reporter else {
+        panic!("Expected a command reporter");
+    };
+    // -C only queries the power mode, anything that reads from the disk would wake it
+    assert_eq!(reporter.command, "hdparm");
+    assert_eq!(
+        reporter.args.as_deref(),
+        Some(&["-C".to_string(), "/dev/sdb".to_string()][..])
+    );
+}
+
+#[tokio::test]
+async fn test_builder_collect_values() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.cpu_usage]
+type = "override"
+disabled = true
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder()
+        .device_name("tower")
+        .config(config)
+        .build()
+        .unwrap();
+
+    let values = stats.collect_values().await;
+    assert!(values.iter().all(|(sensor, _)| sensor.id != "cpu_usage"));
+    let memory_total = values
+        .iter()
+        .find(|(sensor, _)| sensor.id == "memory_total")
+        .and_then(|(_, value)| value.as_deref());
+    assert!(memory_total.is_some_and(|value| value.parse::<f64>().is_ok()));
+}
+
+#[tokio::test]
+async fn test_override_builtin_command() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.array_status]
+type = "override"
+command = "echo"
+args = ["mdState=STARTED"]
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder().config(config).build().unwrap();
+
+    let values = stats.collect_values().await;
+    let array_status = values
+        .iter()
+        .find(|(sensor, _)| sensor.id == "array_status")
+        .and_then(|(_, value)| value.as_deref());
+    assert_eq!(array_status, Some("Started"));
+}
+
+#[tokio::test]
+async fn test_temp_unit_fahrenheit() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.board_temp]
+type = "command"
+name = "Board Temperature"
+command = "echo"
+args = ["40.5"]
+unit = "°C"
+device_class = "temperature"
+disabled = false
+
+[sensors.outside_temp]
+type = "command"
+name = "Outside Temperature"
+command = "echo"
+args = ["70"]
+unit = "°F"
+device_class = "temperature"
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder()
+        .config(config)
+        .temp_unit(TempUnit::F)
+        .disabled_prefixes(vec!["docker".to_string()])
+        .build()
+        .unwrap();
+
+    let values = stats.collect_values().await;
+    let reading = |id: &str| {
+        values
+            .iter()
+            .find(|(sensor, _)| sensor.id == id)
+            .map(|(sensor, value)| (sensor.unit.clone().unwrap(), value.clone().unwrap()))
+            .unwrap()
+    };
+    assert_eq!(
+        reading("board_temp"),
+        ("°F".to_string(), "104.9".to_string())
+    );
+    // already in °F, published as read
+    assert_eq!(
+        reading("outside_temp"),
+        ("°F".to_string(), "70".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_publish_stats_critical_failed() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.array_status]
+type = "override"
+command = "/nonexistent/mdcmd"
+critical = true
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder()
+        .config(config)
+        .json_output(true)
+        .json_array(true)
+        .build()
+        .unwrap();
+
+    let mut sensors = stats.sensors().await;
+    let critical_failed = stats.publish_stats(None, &mut sensors).await.unwrap();
+    assert_eq!(critical_failed, vec!["array_status".to_string()]);
+}
+
+#[tokio::test]
+async fn test_config_command_sensor_collected() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.ups_power]
+type = "command"
+name = "UPS Power"
+unit = "W"
+device_class = "power"
+command = "echo"
+args = ["231.5"]
+post_process = "ParseFloat"
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder().config(config).build().unwrap();
+
+    let values = stats.collect_values().await;
+    let power = values
+        .iter()
+        .find(|(sensor, _)| sensor.id == "ups_power")
+        .and_then(|(_, value)| value.as_deref());
+    assert_eq!(power, Some("231.5"));
+}
+
+#[tokio::test]
+async fn test_static_sensor() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.server_location]
+type = "static"
+name = "Location"
+value = "Basement rack"
+attributes = { owner = "sam" }
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder().config(config).build().unwrap();
+
+    let mut sensors = stats.sensors().await;
+    let sensor = sensors
+        .iter_mut()
+        .find(|sensor| sensor.id == "server_location")
+        .unwrap();
+    let reporter = sensor.reporter.as_mut().unwrap();
+    assert_eq!(reporter.name(), "static");
+    assert_eq!(reporter.get_value().await.as_deref(), Some("Basement rack"));
+    assert_eq!(reporter.get_attributes().unwrap()["owner"], "sam");
+}
+
+#[tokio::test]
+async fn test_memory_pressure_reads_the_file() {
+    let stats = UnraidStats::builder().build().unwrap();
+    let mut sensors = stats.sensors().await;
+    let sensor = sensors
+        .iter_mut()
+        .find(|sensor| sensor.id == "memory_pressure")
+        .unwrap();
+    let Some(SensorReporterType::File(reporter)) = &mut sensor.reporter else {
+        panic!("Expected a file reporter");
+    };
+    assert_eq!(reporter.path.to_str(), Some("/proc/pressure/memory"));
+
+    let path = std::env::temp_dir().join(format!("unraid_pressure_{}", std::process::id()));
+    std::fs::write(
+        &path,
+        "some avg10=1.52 avg60=0.30 avg300=0.07 total=445276\n",
+    )
+    .unwrap();
+    reporter.path = path.clone();
+    let value = sensor.reporter.as_mut().unwrap().get_value().await;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(value.as_deref(), Some("1.52"));
+}
+
+#[tokio::test]
+async fn test_sensors_sorted_by_id() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.zz_last]
+type = "static"
+name = "Last"
+value = "1"
+disabled = false
+
+[sensors.aa_first]
+type = "static"
+name = "First"
+value = "2"
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder().config(config).build().unwrap();
+
+    let ids: Vec<String> = stats.sensors().await.into_iter().map(|s| s.id).collect();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(ids, sorted);
+    assert_eq!(ids.first().map(String::as_str), Some("aa_first"));
+}
+
+#[tokio::test]
+async fn test_device_class_default_icons() {
+    let config: Config = toml::from_str(
+        r#"
+[device_class_icons]
+power = "flash-outline"
+
+[sensors.ups_power]
+type = "command"
+name = "UPS Power"
+unit = "W"
+device_class = "power"
+command = "echo"
+disabled = false
+
+[sensors.case_temp]
+type = "command"
+name = "Case Temperature"
+unit = "°C"
+device_class = "temperature"
+command = "echo"
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder().config(config).build().unwrap();
+
+    let sensors = stats.sensors().await;
+    let icon = |id: &str| {
+        sensors
+            .iter()
+            .find(|sensor| sensor.id == id)
+            .and_then(|sensor| sensor.icon.clone())
+    };
+    assert_eq!(icon("ups_power").as_deref(), Some("flash-outline"));
+    assert_eq!(icon("case_temp").as_deref(), Some("thermometer"));
+    assert_eq!(icon("uptime").as_deref(), Some("clock-outline"));
+}
+
+#[tokio::test]
+async fn test_command_multi_runs_once() {
+    let runs = std::env::temp_dir().join(format!("unraid_multi_{}", std::process::id()));
+    let config: Config = toml::from_str(&format!(
+        r#"
+[sensors.temps]
+type = "command_multi"
+command = "sh"
+args = ["-c", "echo run >> {}; printf 'Tctl:  +51.3°C\nTccd1: +47.0°C  (high = +80.0°C)\n'"]
+disabled = false
+[sensors.temps.sensors.tctl]
+name = "Tctl"
+line = "Tctl:"
+post_process = "FirstNumber"
+[sensors.temps.sensors.ccd1]
+name = "CCD1"
+line = "Tccd1:"
+post_process = "FirstNumber"
+[sensors.temps.sensors.missing]
+name = "Missing"
+line = "Tccd2:"
+"#,
+        runs.display()
+    ))
+    .unwrap();
+    let stats = UnraidStats::builder().config(config).build().unwrap();
+
+    let values = stats.collect_values().await;
+    let value = |id: &str| {
+        values
+            .iter()
+            .find(|(sensor, _)| sensor.id == id)
+            .map(|(_, value)| value.clone())
+    };
+    assert_eq!(value("temps_tctl"), Some(Some("51.3".to_string())));
+    assert_eq!(value("temps_ccd1"), Some(Some("47".to_string())));
+    assert_eq!(value("temps_missing"), Some(None));
+    let run_count = std::fs::read_to_string(&runs).unwrap().lines().count();
+    std::fs::remove_file(&runs).unwrap();
+    assert_eq!(run_count, 1);
+}
+
+#[tokio::test]
+async fn test_sensor_cache_lifetime() {
+    let runs = std::env::temp_dir().join(format!("unraid_cache_{}", std::process::id()));
+    let config: Config = toml::from_str(&format!(
+        r#"
+[sensors.temps]
+type = "command_multi"
+command = "sh"
+args = ["-c", "echo run >> {}; echo 'Tctl: +51.3'"]
+disabled = false
+[sensors.temps.sensors.tctl]
+name = "Tctl"
+line = "Tctl:"
+post_process = "FirstNumber"
+"#,
+        runs.display()
+    ))
+    .unwrap();
+    let stats = UnraidStats::builder().config(config).build().unwrap();
+    let run_count = || std::fs::read_to_string(&runs).unwrap().lines().count();
+
+    // the cached sensors share the command output until the cache is cleared
+    stats.collect_values().await;
+    stats.collect_values().await;
+    assert_eq!(run_count(), 1);
+    // a scrape builds its own sensors without touching the shared cache
+    stats.collect_values_with(&SensorCache::default()).await;
+    assert_eq!(run_count(), 2);
+    stats.collect_values().await;
+    assert_eq!(run_count(), 2);
+    stats.clear_cache();
+    stats.collect_values().await;
+    let count = run_count();
+    std::fs::remove_file(&runs).unwrap();
+    assert_eq!(count, 3);
+}
+
+#[tokio::test]
+async fn test_usage_precision() {
+    for (precision, decimals) in [(0, None), (2, Some(2))] {
+        let stats = UnraidStats::builder()
+            .precision(precision)
+            .disabled_prefixes(vec!["docker".to_string()])
+            .build()
+            .unwrap();
+        let values = stats.collect_values().await;
+        for id in ["cpu_usage", "memory_usage"] {
+            let value = values
+                .iter()
+                .find(|(sensor, _)| sensor.id == id)
+                .and_then(|(_, value)| value.clone())
+                .unwrap();
+            let value_decimals = value.split_once('.').map(|(_, fraction)| fraction.len());
+            assert_eq!(value_decimals, decimals, "{} = {}", id, value);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_json_output_records_qos_and_retain() {
+    let config: Config = toml::from_str(
+        r#"
+[sensors.rack_units]
+type = "static"
+name = "Rack Units"
+value = "4"
+disabled = false
+"#,
+    )
+    .unwrap();
+    let stats = UnraidStats::builder()
+        .config(config)
+        .node_id("tower".to_string())
+        .json_output(true)
+        .json_array(true)
+        .build()
+        .unwrap();
+    let mut sensors = stats.sensors().await;
+    sensors.retain(|sensor| sensor.id == "rack_units");
+    stats.publish_discovery(None, &sensors).await.unwrap();
+    stats.publish_stats(None, &mut sensors).await.unwrap();
+
+    let records = stats.take_json_records();
+    let record = |topic: &str| {
+        records
+            .iter()
+            .find(|record| record["topic"] == topic)
+            .unwrap_or_else(|| panic!("No record for {}", topic))
+            .clone()
+    };
+    let discovery = record("homeassistant/sensor/tower/rack_units/config");
+    assert_eq!(
+        (&discovery["qos"], &discovery["retain"]),
+        (&json!(1), &json!(true))
+    );
+    assert_eq!(
+        record("tower/sensor/rack_units/state"),
+        json!({ "topic": "tower/sensor/rack_units/state", "payload": "4", "qos": 1, "retain": false })
+    );
+    assert!(stats.take_json_records().is_empty());
+}
+
+#[tokio::test]
+async fn test_device_id_suffix_defaults_to_machine_id() {
+    let machine_id = machine_id_suffix().map_or("tower".to_string(), |id| format!("tower_{}", id));
+    for (suffix, identifier) in [
+        (None, machine_id.as_str()),
+        (Some("machine-id"), machine_id.as_str()),
+        (Some("none"), "tower"),
+        (Some("b2"), "tower_b2"),
+    ] {
+        let stats = UnraidStats::builder()
+            .node_id("tower".to_string())
+            .device_id_suffix(suffix.map(str::to_string))
+            .device_sw_version(Some("7.0.1".to_string()))
+            .json_output(true)
+            .json_array(true)
+            .build()
+            .unwrap();
+        let mut sensors = stats.sensors().await;
+        sensors.retain(|sensor| sensor.id == "cpu_usage");
+        stats.publish_discovery(None, &sensors).await.unwrap();
+        let records = stats.take_json_records();
+        let discovery: serde_json::Value =
+            serde_json::from_str(records[0]["payload"].as_str().unwrap()).unwrap();
+        assert_eq!(discovery["device"]["identifiers"], json!([identifier]));
+    }
+}
+
+#[tokio::test]
+async fn test_device_name_in_discovery() {
+    for (device_name, expected) in [(None, "unraid"), (Some("Unraid Tower"), "Unraid Tower")] {
+        let mut builder = UnraidStats::builder()
+            .device_sw_version(Some("7.0.1".to_string()))
+            .json_output(true)
+            .json_array(true);
+        if let Some(device_name) = device_name {
+            builder = builder.device_name(device_name);
+        }
+        let stats = builder.build().unwrap();
+        let mut sensors = stats.sensors().await;
+        sensors.retain(|sensor| sensor.id == "cpu_usage");
+        stats.publish_discovery(None, &sensors).await.unwrap();
+        let records = stats.take_json_records();
+        let discovery: serde_json::Value =
+            serde_json::from_str(records[0]["payload"].as_str().unwrap()).unwrap();
+        // Home Assistant shows `<device name> <entity name>`, so neither may repeat the other
+        assert_eq!(discovery["device"]["name"], expected);
+        assert_eq!(discovery["name"], "CPU Usage");
+        assert_eq!(discovery["has_entity_name"], true);
+    }
+}
+
+#[tokio::test]
+async fn test_switches_only_discovered_when_handled() {
+    let config = r#"
+[sensors.plex]
+type = "switch"
+name = "Plex"
+command_on = "true"
+command_off = "true"
+disabled = false
+"#;
+    for handle_switches in [false, true] {
+        let stats = UnraidStats::builder()
+            .config(toml::from_str(config).unwrap())
+            .handle_switches(handle_switches)
+            .json_output(true)
+            .json_array(true)
+            .build()
+            .unwrap();
+        stats.publish_discovery(None, &[]).await.unwrap();
+        let records = stats.take_json_records();
+        let discovered = records
+            .iter()
+            .any(|record| record["topic"] == "homeassistant/switch/unraid_unraid/plex/config");
+        assert_eq!(discovered, handle_switches);
+    }
+}